@@ -0,0 +1,53 @@
+// Measures the cost of wrapping an error under each sampling mode, next to
+// the cost of boxing it with no wrapper at all. Run with `cargo bench`;
+// prints nanoseconds per wrap.
+
+use backtrace_error::{
+    set_capture_policy, set_sampling, BacktraceError, CapturePolicy, DynBacktraceError, SampleKey,
+    Sampling,
+};
+use std::{error::Error, fmt, hint::black_box, time::Instant};
+
+fn bench<T>(name: &str, iterations: u32, wrap: impl Fn() -> T) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        drop(black_box(wrap()));
+    }
+    let per_wrap = start.elapsed() / iterations;
+    println!("{:<24} {:>10?} per wrap", name, per_wrap);
+}
+
+fn boxed() -> Box<dyn Error + Send + Sync> {
+    Box::new(black_box(fmt::Error))
+}
+
+fn wrapped() -> DynBacktraceError {
+    DynBacktraceError::from(black_box(fmt::Error))
+}
+
+fn main() {
+    bench("baseline (Box<dyn Error>)", 1_000_000, boxed);
+    set_capture_policy(CapturePolicy::Always);
+    set_sampling(Sampling::All);
+    bench("all", 1_000, wrapped);
+    set_sampling(Sampling::OneIn(100));
+    bench("one in 100", 100_000, wrapped);
+    set_sampling(Sampling::OneIn(10_000));
+    bench("one in 10000", 1_000_000, wrapped);
+    set_sampling(Sampling::PerSecond {
+        limit: 10,
+        key: SampleKey::ErrorType,
+    });
+    bench("10/s per error type", 1_000_000, wrapped);
+    set_sampling(Sampling::PerSecond {
+        limit: 10,
+        key: SampleKey::CallSite,
+    });
+    bench("10/s per call site", 1_000_000, wrapped);
+    set_sampling(Sampling::All);
+    set_capture_policy(CapturePolicy::Never);
+    bench("policy never", 1_000_000, wrapped);
+    bench("policy never (typed)", 1_000_000, || {
+        BacktraceError::new(black_box(fmt::Error))
+    });
+}
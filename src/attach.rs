@@ -0,0 +1,130 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! Typed values attached to an error, retrievable by type.
+
+use crate::{carrier, BacktraceError, DynBacktraceError};
+use std::{
+    any::Any,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+/// One attached value, and how to print it if it was attached with
+/// `attach_printable`.
+pub(crate) struct Attachment {
+    value: Box<dyn Any + Send + Sync>,
+    display: Option<fn(&(dyn Any + Send + Sync), &mut Formatter<'_>) -> fmt::Result>,
+}
+
+impl Attachment {
+    fn new<T: Send + Sync + 'static>(value: T) -> Self {
+        Attachment {
+            value: Box::new(value),
+            display: None,
+        }
+    }
+
+    fn printable<T: Display + Send + Sync + 'static>(value: T) -> Self {
+        Attachment {
+            value: Box::new(value),
+            display: Some(display_as::<T>),
+        }
+    }
+
+    pub(crate) fn is_printable(&self) -> bool {
+        self.display.is_some()
+    }
+}
+
+impl Display for Attachment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.display {
+            Some(display) => display(&*self.value, f),
+            None => Ok(()),
+        }
+    }
+}
+
+fn display_as<T: Display + 'static>(
+    value: &(dyn Any + Send + Sync),
+    f: &mut Formatter<'_>,
+) -> fmt::Result {
+    match value.downcast_ref::<T>() {
+        Some(value) => value.fmt(f),
+        None => Ok(()),
+    }
+}
+
+/// The most recently attached `T` in `own`, or failing that in the wrapper
+/// layers inside `inner`, outermost first.
+fn request<'a, T: 'static>(
+    own: &'a [Attachment],
+    inner: &'a (dyn Error + 'static),
+) -> Option<&'a T> {
+    let find = |attachments: &'a [Attachment]| {
+        attachments
+            .iter()
+            .rev()
+            .find_map(|a| a.value.downcast_ref::<T>())
+    };
+    find(own).or_else(|| {
+        carrier::layers(inner)
+            .into_iter()
+            .find_map(|carried| find(&carried.details?.attachments))
+    })
+}
+
+impl<E: Error + 'static> BacktraceError<E> {
+    /// Attaches a value that handlers can later retrieve by type with
+    /// [`Self::request_ref`]: a request ID, a path, a retry count. It is not
+    /// printed in the report; use [`Self::attach_printable`] for that.
+    ///
+    /// ```
+    /// use backtrace_error::BacktraceError;
+    ///
+    /// struct RequestId(u64);
+    ///
+    /// let err = BacktraceError::new(std::fmt::Error).attach(RequestId(42));
+    /// assert_eq!(err.request_ref::<RequestId>().unwrap().0, 42);
+    /// assert!(err.request_ref::<String>().is_none());
+    /// ```
+    pub fn attach<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.details.attachments.push(Attachment::new(value));
+        self
+    }
+
+    /// Like [`Self::attach`], but the value is also printed in the report,
+    /// under "Attachments:".
+    pub fn attach_printable<T: Display + Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.details.attachments.push(Attachment::printable(value));
+        self
+    }
+
+    /// The most recently attached value of type `T`, if any.
+    pub fn request_ref<T: 'static>(&self) -> Option<&T> {
+        request(&self.details.attachments, &self.inner)
+    }
+}
+
+impl DynBacktraceError {
+    /// Attaches a value that handlers can later retrieve by type with
+    /// [`Self::request_ref`]. It is not printed in the report; use
+    /// [`Self::attach_printable`] for that.
+    pub fn attach<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.details.attachments.push(Attachment::new(value));
+        self
+    }
+
+    /// Like [`Self::attach`], but the value is also printed in the report,
+    /// under "Attachments:".
+    pub fn attach_printable<T: Display + Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.details.attachments.push(Attachment::printable(value));
+        self
+    }
+
+    /// The most recently attached value of type `T`, if any.
+    pub fn request_ref<T: 'static>(&self) -> Option<&T> {
+        request(&self.details.attachments, &*self.inner)
+    }
+}
@@ -0,0 +1,343 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! Deciding whether to capture a backtrace when an error is wrapped.
+
+use crate::{carrier, recorder, stats, Details, Metadata};
+use std::{
+    any::{type_name, TypeId},
+    backtrace::Backtrace,
+    collections::hash_map::DefaultHasher,
+    env,
+    error::Error,
+    hash::{Hash, Hasher},
+    panic::Location,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        OnceLock,
+    },
+    time::Instant,
+};
+
+/// When the wrappers capture a backtrace.
+///
+/// The policy is global. It starts out as whatever the
+/// `BACKTRACE_ERROR_CAPTURE` environment variable says (`always`, `never`,
+/// `env` or `debug-only`), or `Env` if that is unset, and can be changed at
+/// any time with [`set_capture_policy`]. This lets a service capture error
+/// backtraces in production without setting `RUST_BACKTRACE`, which would
+/// also turn on backtraces for every panic in the process. Whenever the
+/// policy captures a backtrace it also records [`Metadata`] about when and
+/// where.
+///
+/// [`Metadata`]: crate::Metadata
+///
+/// ```
+/// use backtrace_error::{set_capture_policy, CapturePolicy, DynBacktraceError};
+///
+/// set_capture_policy(CapturePolicy::Always);
+/// let err = DynBacktraceError::from(std::fmt::Error);
+/// assert!(!err.frames().is_empty());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CapturePolicy {
+    /// Always capture, regardless of the environment.
+    Always,
+    /// Never capture.
+    Never,
+    /// Capture if `RUST_LIB_BACKTRACE` or `RUST_BACKTRACE` say so, exactly
+    /// as `Backtrace::capture` does. This was the only behaviour before
+    /// policies existed and is still the default.
+    Env,
+    /// Always capture in builds with debug assertions, never otherwise.
+    DebugOnly,
+}
+
+impl CapturePolicy {
+    fn from_u8(n: u8) -> Option<CapturePolicy> {
+        match n {
+            1 => Some(CapturePolicy::Always),
+            2 => Some(CapturePolicy::Never),
+            3 => Some(CapturePolicy::Env),
+            4 => Some(CapturePolicy::DebugOnly),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            CapturePolicy::Always => 1,
+            CapturePolicy::Never => 2,
+            CapturePolicy::Env => 3,
+            CapturePolicy::DebugOnly => 4,
+        }
+    }
+
+    fn from_env() -> CapturePolicy {
+        let var = env::var("BACKTRACE_ERROR_CAPTURE").unwrap_or_default();
+        match var.trim().to_ascii_lowercase().as_str() {
+            "always" | "1" | "on" | "true" => CapturePolicy::Always,
+            "never" | "0" | "off" | "false" => CapturePolicy::Never,
+            "debug-only" | "debug_only" | "debug" => CapturePolicy::DebugOnly,
+            _ => CapturePolicy::Env,
+        }
+    }
+}
+
+// Zero means "not decided yet": the environment is consulted on first use.
+static POLICY: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the global [`CapturePolicy`], overriding `BACKTRACE_ERROR_CAPTURE`.
+pub fn set_capture_policy(policy: CapturePolicy) {
+    POLICY.store(policy.to_u8(), Ordering::Relaxed);
+}
+
+/// The global [`CapturePolicy`] currently in effect.
+pub fn capture_policy() -> CapturePolicy {
+    if let Some(policy) = CapturePolicy::from_u8(POLICY.load(Ordering::Relaxed)) {
+        return policy;
+    }
+    let policy = CapturePolicy::from_env();
+    // Don't clobber a policy someone set while we were reading the env.
+    let _ = POLICY.compare_exchange(0, policy.to_u8(), Ordering::Relaxed, Ordering::Relaxed);
+    CapturePolicy::from_u8(POLICY.load(Ordering::Relaxed)).unwrap_or(policy)
+}
+
+/// Whether `RUST_LIB_BACKTRACE` or `RUST_BACKTRACE` enable capture, by the
+/// same rules `Backtrace::capture` uses. Cached after the first call, as std
+/// does.
+fn enabled_by_env() -> bool {
+    static ENABLED: AtomicU8 = AtomicU8::new(0);
+    match ENABLED.load(Ordering::Relaxed) {
+        1 => return false,
+        2 => return true,
+        _ => {}
+    }
+    let enabled = match env::var("RUST_LIB_BACKTRACE") {
+        Ok(v) => v != "0",
+        Err(_) => env::var("RUST_BACKTRACE").is_ok_and(|v| v != "0"),
+    };
+    ENABLED.store(if enabled { 2 } else { 1 }, Ordering::Relaxed);
+    enabled
+}
+
+/// How many of the errors that the [`CapturePolicy`] says to capture
+/// backtraces for actually get one.
+///
+/// Capturing a backtrace is expensive, which matters for errors produced
+/// in bulk (say, a parser rejecting bad input). Errors that sampling skips
+/// still get wrapped, but their report says "backtrace not sampled" where
+/// the backtrace would be. Like the policy, sampling is global; it is
+/// changed with [`set_sampling`].
+///
+/// ```
+/// use backtrace_error::{set_capture_policy, set_sampling, CapturePolicy, DynBacktraceError, Sampling};
+///
+/// set_capture_policy(CapturePolicy::Always);
+/// set_sampling(Sampling::OneIn(100));
+/// let captured = (0..1000)
+///     .map(|_| DynBacktraceError::from(std::fmt::Error))
+///     .filter(|err| !err.frames().is_empty())
+///     .count();
+/// assert_eq!(captured, 10);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sampling {
+    /// Capture every time (the default).
+    All,
+    /// Capture one time in every `n`, counted across all errors.
+    OneIn(u32),
+    /// Capture at most `limit` times per second for each distinct `key`.
+    PerSecond { limit: u32, key: SampleKey },
+}
+
+/// What [`Sampling::PerSecond`] counts errors by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SampleKey {
+    /// The type of the wrapped error.
+    ErrorType,
+    /// The source location where the error was wrapped (usually a `?`).
+    CallSite,
+}
+
+// Packed into one word so the hot path is a single atomic load: the mode in
+// the top byte, the key in the next, and `n` or `limit` in the low 32 bits.
+static SAMPLING: AtomicU64 = AtomicU64::new(0);
+
+impl Sampling {
+    fn to_u64(self) -> u64 {
+        match self {
+            Sampling::All => 0,
+            Sampling::OneIn(n) => 1 << 56 | u64::from(n),
+            Sampling::PerSecond { limit, key } => {
+                let key = match key {
+                    SampleKey::ErrorType => 0,
+                    SampleKey::CallSite => 1,
+                };
+                2 << 56 | key << 48 | u64::from(limit)
+            }
+        }
+    }
+
+    fn from_u64(n: u64) -> Sampling {
+        let value = n as u32;
+        match n >> 56 {
+            1 => Sampling::OneIn(value),
+            2 => Sampling::PerSecond {
+                limit: value,
+                key: if (n >> 48) & 0xff == 0 {
+                    SampleKey::ErrorType
+                } else {
+                    SampleKey::CallSite
+                },
+            },
+            _ => Sampling::All,
+        }
+    }
+}
+
+/// Sets the global [`Sampling`].
+pub fn set_sampling(sampling: Sampling) {
+    SAMPLING.store(sampling.to_u64(), Ordering::Relaxed);
+}
+
+/// The global [`Sampling`] currently in effect.
+pub fn sampling() -> Sampling {
+    Sampling::from_u64(SAMPLING.load(Ordering::Relaxed))
+}
+
+#[derive(Hash)]
+enum Key {
+    Type(TypeId),
+    Site(&'static Location<'static>),
+}
+
+/// One key's rate-limit window: the key's hash (zero while the slot is
+/// free), and the second it last counted in packed above how many
+/// captures that second has had so far.
+struct Window {
+    key: AtomicU64,
+    state: AtomicU64,
+}
+
+// Only used to fill `WINDOWS`; each element is a fresh copy.
+#[allow(clippy::declare_interior_mutable_const)]
+const FREE: Window = Window {
+    key: AtomicU64::new(0),
+    state: AtomicU64::new(0),
+};
+
+/// Open-addressed by key hash and never evicted, since a program only has
+/// so many error types and call sites. Should it have more than this, the
+/// extra keys share the window their hash first lands on.
+const WINDOWS_LEN: usize = 1024;
+static WINDOWS: [Window; WINDOWS_LEN] = [FREE; WINDOWS_LEN];
+
+fn window(key: Key) -> &'static Window {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    let hash = hasher.finish().max(1);
+    let start = hash as usize % WINDOWS_LEN;
+    for i in 0..WINDOWS_LEN {
+        let window = &WINDOWS[(start + i) % WINDOWS_LEN];
+        let claimed = window
+            .key
+            .compare_exchange(0, hash, Ordering::Relaxed, Ordering::Relaxed);
+        if claimed.is_ok() || claimed == Err(hash) {
+            return window;
+        }
+    }
+    &WINDOWS[start]
+}
+
+/// Whole seconds since the first rate-limited capture, from the monotonic
+/// clock so that wall-clock adjustments can't reset or stall a window.
+fn second() -> u32 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_secs() as u32
+}
+
+fn sampled(type_id: TypeId, location: &'static Location<'static>) -> bool {
+    match sampling() {
+        Sampling::All => true,
+        Sampling::OneIn(n) => {
+            static COUNTER: AtomicU64 = AtomicU64::new(0);
+            COUNTER.fetch_add(1, Ordering::Relaxed) % u64::from(n.max(1)) == 0
+        }
+        Sampling::PerSecond { limit, key } => {
+            let key = match key {
+                SampleKey::ErrorType => Key::Type(type_id),
+                SampleKey::CallSite => Key::Site(location),
+            };
+            let window = window(key);
+            let now = second();
+            let mut state = window.state.load(Ordering::Relaxed);
+            loop {
+                let count = if (state >> 32) as u32 == now {
+                    state as u32
+                } else {
+                    0
+                };
+                if count >= limit {
+                    return false;
+                }
+                let next = u64::from(now) << 32 | u64::from(count + 1);
+                match window.state.compare_exchange_weak(
+                    state,
+                    next,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return true,
+                    Err(current) => state = current,
+                }
+            }
+        }
+    }
+}
+
+/// Captures the backtrace for a newly wrapped `err` according to the
+/// current policy and sampling, and sets up the details to go with it. If
+/// `err` already carries a backtrace, or is already one of our wrappers, no
+/// new one is captured.
+pub(crate) fn capture<E: Error + 'static>(
+    err: &E,
+    location: &'static Location<'static>,
+) -> (Box<Backtrace>, Box<Details>) {
+    let mut details = Box::new(Details::new(location, type_name::<E>()));
+    let wanted = match capture_policy() {
+        CapturePolicy::Always => true,
+        CapturePolicy::Never => false,
+        CapturePolicy::Env => enabled_by_env(),
+        CapturePolicy::DebugOnly => cfg!(debug_assertions),
+    };
+    let layers = carrier::layers_of(err);
+    if layers.iter().any(|carried| carried.details.is_some()) {
+        // Already wrapped by us: whether it got a backtrace, and counting
+        // and recording it, was settled then.
+        return (Box::new(Backtrace::disabled()), details);
+    }
+    if let Some(carried) = layers.last() {
+        // A user type that carries its own backtrace, seen for the first time.
+        if wanted {
+            details.metadata = Some(Box::new(Metadata::capture()));
+        }
+        stats::record(err, carried.backtrace, &details);
+        recorder::record(err, carried.backtrace, &details);
+        return (Box::new(Backtrace::disabled()), details);
+    }
+    // Metadata is only worth its cost alongside a backtrace, so errors that
+    // sampling skips go without.
+    let backtrace = if !wanted {
+        Backtrace::disabled()
+    } else if sampled(TypeId::of::<E>(), location) {
+        details.metadata = Some(Box::new(Metadata::capture()));
+        Backtrace::force_capture()
+    } else {
+        details.unsampled = true;
+        Backtrace::disabled()
+    };
+    stats::record(err, &backtrace, &details);
+    recorder::record(err, &backtrace, &details);
+    (Box::new(backtrace), details)
+}
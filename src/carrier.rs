@@ -0,0 +1,285 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! Tracking of error types that already carry a backtrace.
+//!
+//! Stable Rust gives us no way to ask an arbitrary `E: Error` whether it also
+//! implements some other trait, nor to get the concrete type out of a
+//! `&dyn Error` without naming it. So types that carry a backtrace are
+//! recorded here, in a small global list of lookup functions that each try
+//! one `downcast_ref` and, on success, hand back the carried backtrace.
+//!
+//! Our own `BacktraceError<E>` types register themselves the first time one
+//! is constructed; user types opt in with `register_has_backtrace`.
+
+use crate::{BacktraceError, Details, DynBacktraceError, Metadata};
+use std::{
+    any::TypeId,
+    backtrace::{Backtrace, BacktraceStatus},
+    cell::RefCell,
+    collections::HashSet,
+    error::Error,
+    hash::{BuildHasherDefault, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        PoisonError, RwLock,
+    },
+};
+
+/// An error type that may already hold a captured backtrace.
+///
+/// When a type implementing this is registered with
+/// [`register_has_backtrace`], wrapping one of its values in a
+/// `BacktraceError` or `DynBacktraceError` reuses the backtrace it carries
+/// instead of capturing (and later printing) a second one. Only a captured
+/// backtrace is reused; if the carried one is disabled or unsupported, the
+/// wrapper captures its own as the capture policy says.
+///
+/// ```
+/// use backtrace_error::{register_has_backtrace, DynBacktraceError, HasBacktrace};
+/// use std::{backtrace::Backtrace, fmt};
+///
+/// #[derive(Debug)]
+/// struct LoadError {
+///     backtrace: Backtrace,
+/// }
+///
+/// impl fmt::Display for LoadError {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         f.write_str("load failed")
+///     }
+/// }
+///
+/// impl std::error::Error for LoadError {}
+///
+/// impl HasBacktrace for LoadError {
+///     fn backtrace(&self) -> Option<&Backtrace> {
+///         Some(&self.backtrace)
+///     }
+/// }
+///
+/// register_has_backtrace::<LoadError>();
+///
+/// let err = DynBacktraceError::from(LoadError {
+///     backtrace: Backtrace::force_capture(),
+/// });
+/// let own = HasBacktrace::backtrace(&err).unwrap();
+/// assert!(std::ptr::eq(own, &err.downcast_ref::<LoadError>().unwrap().backtrace));
+/// ```
+pub trait HasBacktrace {
+    /// The backtrace carried by this value, if any.
+    fn backtrace(&self) -> Option<&Backtrace>;
+}
+
+impl<E: Error + 'static> HasBacktrace for BacktraceError<E> {
+    fn backtrace(&self) -> Option<&Backtrace> {
+        Some(backtrace(&self.inner, &self.backtrace))
+    }
+}
+
+impl HasBacktrace for DynBacktraceError {
+    fn backtrace(&self) -> Option<&Backtrace> {
+        Some(DynBacktraceError::backtrace(self))
+    }
+}
+
+/// What a registered lookup finds in an error: the error to print as the
+/// message (the wrapped error, for our own wrappers), its backtrace, and,
+/// for our own wrappers, everything else they recorded.
+pub(crate) struct Carried<'a> {
+    pub(crate) error: &'a (dyn Error + 'static),
+    pub(crate) backtrace: &'a Backtrace,
+    pub(crate) details: Option<&'a Details>,
+}
+
+type Lookup = for<'a> fn(&'a (dyn Error + 'static)) -> Option<Carried<'a>>;
+
+static CARRIERS: RwLock<Vec<(TypeId, Lookup)>> = RwLock::new(Vec::new());
+
+/// A Bloom filter over the types in `CARRIERS`, so that wrapping an error of
+/// a type that was never registered, which is most of them, can skip the
+/// lock. A false positive only costs the lookup it would have done anyway.
+const FILTER_BITS: usize = 4096;
+// Only used to fill `FILTER`; each element is a fresh copy.
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: AtomicU64 = AtomicU64::new(0);
+static FILTER: [AtomicU64; FILTER_BITS / 64] = [EMPTY; FILTER_BITS / 64];
+
+/// `TypeId`s are hashes already, so this passes one through rather than
+/// hashing it again.
+#[derive(Default)]
+struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(b);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 ^= n;
+    }
+}
+
+fn filter_bits(id: TypeId) -> [usize; 2] {
+    let mut hasher = IdHasher::default();
+    id.hash(&mut hasher);
+    let hash = hasher.finish();
+    [
+        hash as usize % FILTER_BITS,
+        (hash >> 32) as usize % FILTER_BITS,
+    ]
+}
+
+fn may_be_registered(id: TypeId) -> bool {
+    filter_bits(id)
+        .iter()
+        .all(|&bit| FILTER[bit / 64].load(Ordering::Acquire) & 1 << (bit % 64) != 0)
+}
+
+/// Records `T` as a type whose values may carry a backtrace, so that wrapping
+/// one reuses that backtrace rather than capturing a new one. Registering
+/// the same type more than once is harmless.
+pub fn register_has_backtrace<T: HasBacktrace + Error + 'static>() {
+    register(TypeId::of::<T>(), lookup::<T>);
+}
+
+pub(crate) fn register_wrapper<E: Error + 'static>() {
+    // Called on every wrap, so each thread remembers what it has registered
+    // rather than going to `CARRIERS` every time.
+    thread_local! {
+        static REGISTERED: RefCell<HashSet<TypeId, BuildHasherDefault<IdHasher>>> =
+            RefCell::default();
+    }
+    let id = TypeId::of::<BacktraceError<E>>();
+    if REGISTERED.with(|registered| registered.borrow_mut().insert(id)) {
+        register(id, lookup_wrapper::<E>);
+    }
+}
+
+fn register(id: TypeId, lookup: Lookup) {
+    let registered = CARRIERS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .any(|(other, _)| *other == id);
+    if !registered {
+        let mut carriers = CARRIERS.write().unwrap_or_else(PoisonError::into_inner);
+        if !carriers.iter().any(|(other, _)| *other == id) {
+            carriers.push((id, lookup));
+            for bit in filter_bits(id) {
+                FILTER[bit / 64].fetch_or(1 << (bit % 64), Ordering::Release);
+            }
+        }
+    }
+}
+
+fn lookup<'a, T: HasBacktrace + Error + 'static>(
+    err: &'a (dyn Error + 'static),
+) -> Option<Carried<'a>> {
+    let backtrace = err.downcast_ref::<T>()?.backtrace()?;
+    if !captured(backtrace) {
+        return None;
+    }
+    Some(Carried {
+        error: err,
+        backtrace,
+        details: None,
+    })
+}
+
+fn lookup_wrapper<'a, E: Error + 'static>(err: &'a (dyn Error + 'static)) -> Option<Carried<'a>> {
+    let wrapper = err.downcast_ref::<BacktraceError<E>>()?;
+    Some(Carried {
+        error: &wrapper.inner,
+        backtrace: &wrapper.backtrace,
+        details: Some(&wrapper.details),
+    })
+}
+
+fn lookup_once<'a>(err: &'a (dyn Error + 'static)) -> Option<Carried<'a>> {
+    let carriers = CARRIERS.read().unwrap_or_else(PoisonError::into_inner);
+    carriers.iter().find_map(|(_, lookup)| lookup(err))
+}
+
+/// Looks for a backtrace already carried by `err`. Our own wrappers only hold
+/// a placeholder when their wrapped error is itself a carrier, so those are
+/// followed inward to the innermost carried backtrace.
+pub(crate) fn find<'a>(err: &'a (dyn Error + 'static)) -> Option<Carried<'a>> {
+    let mut found = lookup_once(err)?;
+    while let Some(inner) = step_inward(&found) {
+        found = inner;
+    }
+    Some(found)
+}
+
+/// Like `find`, but returns every carrier passed on the way in, outermost
+/// first, so that their context can be gathered too.
+pub(crate) fn layers<'a>(err: &'a (dyn Error + 'static)) -> Vec<Carried<'a>> {
+    let mut layers: Vec<Carried<'a>> = lookup_once(err).into_iter().collect();
+    while let Some(inner) = layers.last().and_then(step_inward) {
+        layers.push(inner);
+    }
+    layers
+}
+
+/// `layers` for a value of a known type, which skips the lookup entirely
+/// when that type was never registered.
+pub(crate) fn layers_of<E: Error + 'static>(err: &E) -> Vec<Carried<'_>> {
+    if may_be_registered(TypeId::of::<E>()) {
+        layers(err)
+    } else {
+        Vec::new()
+    }
+}
+
+fn step_inward<'a>(found: &Carried<'a>) -> Option<Carried<'a>> {
+    // A user carrier hands back itself as the error; only our own wrappers
+    // have something further in to look at.
+    if found.details.is_some() {
+        lookup_once(found.error)
+    } else {
+        None
+    }
+}
+
+/// The details recorded by the innermost of our own wrappers inside `err`,
+/// or `own` if there are none: the ID and location there describe where the
+/// error was first wrapped.
+pub(crate) fn origin<'a>(err: &'a (dyn Error + 'static), own: &'a Details) -> &'a Details {
+    layers(err)
+        .into_iter()
+        .rev()
+        .find_map(|carried| carried.details)
+        .unwrap_or(own)
+}
+
+/// The metadata recorded by the innermost of our own wrappers inside `err`
+/// that recorded any, or by `own`: only a wrapper that captured a backtrace
+/// records metadata, and it goes with that backtrace.
+pub(crate) fn metadata<'a>(
+    err: &'a (dyn Error + 'static),
+    own: &'a Details,
+) -> Option<&'a Metadata> {
+    layers(err)
+        .into_iter()
+        .rev()
+        .filter_map(|carried| carried.details)
+        .chain(Some(own))
+        .find_map(|details| details.metadata.as_deref())
+}
+
+/// The backtrace of a wrapper around `err` whose own is `own`: the one
+/// carried inside `err`, if there is one.
+pub(crate) fn backtrace<'a>(err: &'a (dyn Error + 'static), own: &'a Backtrace) -> &'a Backtrace {
+    find(err).map_or(own, |carried| carried.backtrace)
+}
+
+fn captured(backtrace: &Backtrace) -> bool {
+    backtrace.status() == BacktraceStatus::Captured
+}
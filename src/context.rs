@@ -0,0 +1,76 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+use crate::{BacktraceError, DynBacktraceError};
+use std::{error::Error, fmt::Display, panic::Location};
+
+/// Adds a message describing what was being done when an error occurred.
+///
+/// Messages are pushed onto a stack kept in the wrapper and printed above
+/// the backtrace, innermost first. The backtrace itself is only captured
+/// once, where the underlying error was first wrapped.
+///
+/// On a plain `Result<T, E>` this wraps the error in a `BacktraceError<E>`
+/// (capturing a backtrace). On a `Result<T, DynBacktraceError>` it pushes
+/// onto the existing wrapper. On a `Result<T, BacktraceError<E>>` it nests
+/// one `BacktraceError` in another without capturing again; `?` flattens
+/// that back into a `BacktraceError<E>` with the context kept, but only one
+/// level of it, so calling `context` twice on a typed result doesn't
+/// convert back. To add to a typed wrapper in place, any number of times,
+/// use `.map_err(|err| err.push_context(..))` (see
+/// [`BacktraceError::push_context`]).
+///
+/// ```
+/// use backtrace_error::{BacktraceError, Context, DynBacktraceError};
+/// use std::{fs, io};
+///
+/// fn read_config(path: &str) -> Result<String, BacktraceError<io::Error>> {
+///     fs::read_to_string(path).with_context(|| format!("reading {}", path))
+/// }
+///
+/// fn load() -> Result<String, BacktraceError<io::Error>> {
+///     read_config("/does-not-exist.toml").map_err(|err| err.push_context("loading config"))
+/// }
+///
+/// fn start() -> Result<String, DynBacktraceError> {
+///     load().map_err(BacktraceError::into_dyn).context("starting up")
+/// }
+///
+/// let err = start().unwrap_err();
+/// assert_eq!(
+///     err.context(),
+///     ["reading /does-not-exist.toml", "loading config", "starting up"]
+/// );
+/// ```
+pub trait Context<T> {
+    type Error;
+    fn context<C: Display>(self, context: C) -> Result<T, Self::Error>;
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T, Self::Error>;
+}
+
+impl<T, E: Error + 'static> Context<T> for Result<T, E> {
+    type Error = BacktraceError<E>;
+    #[track_caller]
+    fn context<C: Display>(self, context: C) -> Result<T, BacktraceError<E>> {
+        self.with_context(|| context)
+    }
+    #[track_caller]
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T, BacktraceError<E>> {
+        let location = Location::caller();
+        self.map_err(|err| {
+            let mut err = BacktraceError::capture_at(err, location);
+            err.details.context.push(f().to_string());
+            err
+        })
+    }
+}
+
+impl<T> Context<T> for Result<T, DynBacktraceError> {
+    type Error = DynBacktraceError;
+    fn context<C: Display>(self, context: C) -> Result<T, DynBacktraceError> {
+        self.with_context(|| context)
+    }
+    fn with_context<C: Display, F: FnOnce() -> C>(self, f: F) -> Result<T, DynBacktraceError> {
+        self.map_err(|err| err.push_context(f()))
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! Stable hashes for grouping occurrences of the same error.
+
+use crate::{carrier, BacktraceError, Details, DynBacktraceError};
+use std::{error::Error, fmt};
+
+/// A hash identifying "the same error": the same error type first wrapped at
+/// the same source location.
+///
+/// It covers the error's type name and the source location it was first
+/// wrapped at (usually a `?`), which every occurrence has whether or not
+/// the capture policy or sampling gave it a backtrace. So occurrences group
+/// the same either way, and the fingerprint is stable across runs and
+/// across builds that don't move that line. Printed as 16 hex digits.
+///
+/// Nothing above that location counts: errors wrapped by a `?` inside a
+/// helper share one fingerprint whichever caller the helper was called
+/// from. Tell those apart by their backtraces or context.
+///
+/// ```
+/// use backtrace_error::DynBacktraceError;
+///
+/// fn fail() -> DynBacktraceError {
+///     DynBacktraceError::from(std::fmt::Error)
+/// }
+///
+/// assert_eq!(fail().fingerprint(), fail().fingerprint());
+/// assert_ne!(
+///     fail().fingerprint(),
+///     DynBacktraceError::from(std::io::Error::new(std::io::ErrorKind::Other, "x")).fingerprint()
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint(pub u64);
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// 64-bit FNV-1a: tiny, and unlike std's hashers, specified, so the same
+/// input hashes the same everywhere.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        // Separate fields, so that ("ab", "c") and ("a", "bc") differ.
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+}
+
+pub(crate) fn compute(details: &Details) -> Fingerprint {
+    let mut hash = Fnv::new();
+    hash.write(details.type_name.as_bytes());
+    let location = details.location;
+    hash.write(location.file().as_bytes());
+    hash.write(&location.line().to_le_bytes());
+    hash.write(&location.column().to_le_bytes());
+    Fingerprint(hash.0)
+}
+
+impl<E: Error + 'static> BacktraceError<E> {
+    /// A hash of this error's type and origin, for grouping occurrences of
+    /// the same error. See [`Fingerprint`].
+    pub fn fingerprint(&self) -> Fingerprint {
+        compute(carrier::origin(&self.inner, &self.details))
+    }
+}
+
+impl DynBacktraceError {
+    /// A hash of this error's type and origin, for grouping occurrences of
+    /// the same error. See [`Fingerprint`].
+    pub fn fingerprint(&self) -> Fingerprint {
+        compute(carrier::origin(&*self.inner, &self.details))
+    }
+}
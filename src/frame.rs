@@ -0,0 +1,229 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! A structured view of the frames in a captured backtrace.
+//!
+//! `std::backtrace::Backtrace` only exposes its frames on nightly, so on
+//! stable we recover them by parsing its (alternate, "full") `Display`
+//! output, which lists one symbol per numbered line, optionally prefixed by
+//! the instruction pointer, with an indented `at file:line:col` line below.
+
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    fmt,
+    ops::Range,
+};
+
+/// One resolved symbol in a backtrace.
+///
+/// Fields std did not resolve (or did not print, on older toolchains that
+/// omit instruction pointers) are `None`. A single machine frame can
+/// resolve to several symbols when functions were inlined into it; all but
+/// the last of those (the physical caller) are marked `is_inlined`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Frame {
+    pub symbol: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub ip: Option<usize>,
+    pub is_inlined: bool,
+}
+
+impl Frame {
+    /// Parses the frames out of `backtrace`, returning an empty list unless
+    /// it was actually captured.
+    ///
+    /// ```
+    /// use backtrace_error::Frame;
+    /// use std::backtrace::Backtrace;
+    ///
+    /// let frames = Frame::from_backtrace(&Backtrace::force_capture());
+    /// assert!(frames.iter().any(|f| f.symbol.as_deref().map_or(false, |s| s.contains("main"))));
+    /// assert!(Frame::from_backtrace(&Backtrace::disabled()).is_empty());
+    /// ```
+    pub fn from_backtrace(backtrace: &Backtrace) -> Vec<Frame> {
+        if backtrace.status() != BacktraceStatus::Captured {
+            return Vec::new();
+        }
+        parse(&format!("{:#}", backtrace))
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol.as_deref().unwrap_or("<unknown>"))?;
+        if let Some(file) = &self.file {
+            write!(f, " at {}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+                if let Some(column) = self.column {
+                    write!(f, ":{}", column)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse(text: &str) -> Vec<Frame> {
+    let mut frames: Vec<Frame> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(location) = line.strip_prefix("at ") {
+            if let Some(frame) = frames.last_mut() {
+                parse_location(frame, location);
+            }
+            continue;
+        }
+        let Some(rest) = strip_index(line) else {
+            continue;
+        };
+        let (ip, symbol) = match rest.split_once(" - ") {
+            Some((ip, symbol)) if ip.trim().starts_with("0x") => {
+                (usize::from_str_radix(&ip.trim()[2..], 16).ok(), symbol)
+            }
+            _ => (None, rest),
+        };
+        if let (Some(ip), Some(prev)) = (ip, frames.last_mut()) {
+            if prev.ip == Some(ip) {
+                prev.is_inlined = true;
+            }
+        }
+        frames.push(Frame {
+            symbol: normalize_symbol(symbol.trim()),
+            file: None,
+            line: None,
+            column: None,
+            ip,
+            is_inlined: false,
+        });
+    }
+    frames
+}
+
+/// Strips a leading `"12: "` frame index, returning `None` for lines that
+/// don't start with one.
+fn strip_index(line: &str) -> Option<&str> {
+    let (index, rest) = line.split_once(':')?;
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(rest.trim_start())
+}
+
+fn parse_location(frame: &mut Frame, location: &str) {
+    // Split from the right: the path itself may contain colons.
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next().and_then(|s| s.parse().ok());
+    let line = parts.next().and_then(|s| s.parse().ok());
+    match (parts.next(), line, column) {
+        (Some(file), Some(line), Some(column)) => {
+            frame.file = Some(file.to_string());
+            frame.line = Some(line);
+            frame.column = Some(column);
+        }
+        _ => frame.file = Some(location.to_string()),
+    }
+}
+
+/// Removes the parts of a symbol name that change from build to build: the
+/// trailing `::h0123456789abcdef` hash and `[0123456789abcdef]` crate
+/// disambiguators. Unresolved symbols become `None`.
+fn normalize_symbol(symbol: &str) -> Option<String> {
+    if symbol.is_empty() || symbol == "<unknown>" {
+        return None;
+    }
+    let symbol = match symbol.rsplit_once("::h") {
+        Some((head, hash)) if is_hash(hash) => head,
+        _ => symbol,
+    };
+    let mut out = String::with_capacity(symbol.len());
+    let mut rest = symbol;
+    while let Some(open) = rest.find('[') {
+        let close = rest[open..].find(']').map(|i| open + i);
+        match close {
+            Some(close) if is_hash(&rest[open + 1..close]) => {
+                out.push_str(&rest[..open]);
+                rest = &rest[close + 1..];
+            }
+            _ => {
+                out.push_str(&rest[..=open]);
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+fn is_hash(s: &str) -> bool {
+    s.len() == 16 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// The part of `frames` worth showing by default: everything between the
+/// machinery that captured the backtrace (std's capture code, our `From`
+/// impls and `?` glue) at the top, and the runtime's startup code at the
+/// bottom. Falls back to all frames if that would leave nothing.
+pub(crate) fn interesting_range(frames: &[Frame]) -> Range<usize> {
+    let symbol = |i: usize| frames[i].symbol.as_deref().unwrap_or("");
+    let mut start = frames
+        .iter()
+        .rposition(|f| is_marker(f, "__rust_end_short_backtrace"))
+        .map_or(0, |i| i + 1);
+    while start < frames.len() && (is_capture_internal(symbol(start)) || is_glue(symbol(start))) {
+        start += 1;
+    }
+    let mut end = match frames[start..]
+        .iter()
+        .position(|f| is_marker(f, "__rust_begin_short_backtrace"))
+    {
+        Some(i) => start + i,
+        None => frames[start..]
+            .iter()
+            .position(|f| is_runtime_startup(f.symbol.as_deref().unwrap_or("")))
+            .map_or(frames.len(), |i| start + i),
+    };
+    while end > start && is_glue(symbol(end - 1)) {
+        end -= 1;
+    }
+    if start < end {
+        start..end
+    } else {
+        0..frames.len()
+    }
+}
+
+fn is_marker(frame: &Frame, marker: &str) -> bool {
+    frame.symbol.as_deref().is_some_and(|s| s.contains(marker))
+}
+
+fn is_capture_internal(symbol: &str) -> bool {
+    const PREFIXES: &[&str] = &[
+        "std::backtrace::",
+        "<std::backtrace::",
+        "std::backtrace_rs::",
+        "backtrace_error::",
+        "<backtrace_error::",
+    ];
+    PREFIXES.iter().any(|p| symbol.starts_with(p)) || symbol.contains(" as backtrace_error::")
+}
+
+fn is_glue(symbol: &str) -> bool {
+    const PREFIXES: &[&str] = &[
+        "core::result::",
+        "<core::result::Result<",
+        "core::convert::",
+        "<T as core::convert::",
+        "core::ops::function::",
+    ];
+    PREFIXES.iter().any(|p| symbol.starts_with(p))
+        || symbol.contains(" as core::ops::function::FnOnce<")
+}
+
+fn is_runtime_startup(symbol: &str) -> bool {
+    symbol.starts_with("std::rt::lang_start")
+        || symbol == "main"
+        || symbol == "__libc_start_main"
+        || symbol == "_start"
+}
@@ -0,0 +1,60 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! Process-unique IDs for telling error instances apart in logs.
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hash, Hasher},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+/// Identifies one wrapped error, so that the report printed in one place
+/// and a log line written in another can be matched up.
+///
+/// It is a per-process prefix, random and mixed with the process ID so that
+/// separate runs and processes are unlikely to collide, plus a counter.
+/// Printed as the prefix in hex and the counter in decimal, e.g.
+/// `3fa1c2d0-17`, in the first line of the report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ErrorId {
+    pub process: u32,
+    pub seq: u64,
+}
+
+// Zero means "not chosen yet".
+static PROCESS: AtomicU32 = AtomicU32::new(0);
+static SEQ: AtomicU64 = AtomicU64::new(1);
+
+impl ErrorId {
+    pub(crate) fn next() -> ErrorId {
+        ErrorId {
+            process: process_prefix(),
+            seq: SEQ.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+fn process_prefix() -> u32 {
+    let prefix = PROCESS.load(Ordering::Relaxed);
+    if prefix != 0 {
+        return prefix;
+    }
+    // `RandomState` is seeded from the OS's random source.
+    let mut hasher = RandomState::new().build_hasher();
+    std::process::id().hash(&mut hasher);
+    SystemTime::now().hash(&mut hasher);
+    let prefix = (hasher.finish() as u32).max(1);
+    match PROCESS.compare_exchange(0, prefix, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => prefix,
+        Err(chosen) => chosen,
+    }
+}
+
+impl fmt::Display for ErrorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}-{}", self.process, self.seq)
+    }
+}
@@ -2,18 +2,36 @@
 // Licensed under ASL2 or MIT
 
 //!
-//! This is a tiny crate that provides a tiny error-wrapper struct
-//! `BacktraceError` with only two features:
+//! This crate provides an error-wrapper struct `BacktraceError` built around
+//! two features:
 //!
 //!   - Captures a backtrace on `From`-conversion from its wrapped type (if
-//!     `RUST_BACKTRACE` is on etc.)
-//!   - Pretty-prints that backtrace in its `Display` implementation.
+//!     `RUST_BACKTRACE` is on etc., or as configured by `set_capture_policy`
+//!     or `BACKTRACE_ERROR_CAPTURE`, and optionally only for a sample of errors
+//!     as configured by `set_sampling`)
+//!   - Pretty-prints that backtrace, along with the chain of `source()` errors
+//!     that led to it, in its `Display` implementation.
+//!     Frames belonging to the capture machinery and to the runtime's startup
+//!     code are hidden unless formatted with `{:#}` or `RUST_BACKTRACE=full`.
 //!
 //! It also includes an extension trait `ResultExt` that you can `use` to give
 //! you `.unwrap_or_backtrace` and `.expect_or_backtrace` methods on any
-//! `Result<T, BacktraceError<E>>`. These methods do do the same as `unwrap`
-//! or `expect` on `Result` except they pretty-print the backtrace on `Err`,
-//! before panicking.
+//! `Result<T, BacktraceError<E>>` or `Result<T, DynBacktraceError>`. These
+//! methods do do the same as `unwrap` or `expect` on `Result` except they
+//! pretty-print the backtrace on `Err`, before panicking.
+//!
+//! How a report is printed can be adjusted. On a terminal it is colored unless
+//! `NO_COLOR` is set, and `BACKTRACE_ERROR_HYPERLINKS=1` or
+//! `BACKTRACE_ERROR_EDITOR` make frame locations clickable. `err.report()`
+//! returns a `Report` that can also show source snippets, or print quickfix
+//! lines for editors or a single line for log shippers; see `Report` for each
+//! option.
+//!
+//! With the `serde` feature, both wrappers implement `Serialize`, producing the
+//! structured `ErrorRecord` schema (message, sources, frames, context and
+//! metadata). Deserializing that into a `RemoteBacktraceError` in another process
+//! gives an error that prints the remote backtrace like a local one, and that a
+//! local `DynBacktraceError` can wrap to show both backtraces.
 //!
 //! Finally, it provides a _dynamic_ variant in case you want to type-erase the
 //! error type, `DynBacktraceError`. This works the same as `BacktraceError<E>`
@@ -22,6 +40,35 @@
 //! more flexible and usable as an "any error" catchall type since it has an
 //! `impl<E:Error + Send + Sync + 'static> From<E>` conversion.
 //!
+//! Wrapping an error that already carries a backtrace (another `BacktraceError`,
+//! or any type implementing `HasBacktrace` that has been registered with
+//! `register_has_backtrace`) reuses that backtrace rather than capturing a
+//! second one.
+//!
+//! Every wrapper also records the source location it was created at (the `?`,
+//! `BacktraceError::new` or `.bt()` call), which costs next to nothing and is
+//! printed in place of the backtrace when none was captured. When a backtrace
+//! is captured, so is the time, thread and process it was captured on. Each
+//! error also gets a process-unique `id()`, printed at the top of its report
+//! so that it can be found again in logs, and a `fingerprint()` that is the same
+//! for every occurrence of the same error type first wrapped at the same place.
+//! After `enable_stats(limit)`, occurrences are tallied by fingerprint in a
+//! bounded in-process registry, readable with `stats_snapshot()`. After
+//! `enable_flight_recorder(n)` the last `n` errors are kept, per thread and
+//! overall, and `install_panic_hook()` prints them before any panic message.
+//!
+//! The `Context` trait adds `.context(msg)` and `.with_context(|| msg)` to
+//! results, pushing messages about what was being done onto a stack kept in
+//! the wrapper and printed above the backtrace.
+//!
+//! Both wrappers can also carry typed values (request IDs, paths, status
+//! codes) with `.attach(value)`, retrieved by type with `.request_ref::<T>()`;
+//! values attached with `.attach_printable(value)` are printed in the report too.
+//!
+//! The `Trace` trait adds `.trace()` to results holding a wrapper, recording
+//! each place the error is returned through; these are printed after the
+//! backtrace, following the error across threads and callbacks.
+//!
 //! # Example
 //!
 //! Usage is straightforward: put some existing error type in it. No macros!
@@ -94,22 +141,173 @@ use std::{
     error::Error,
     fmt::{Debug, Display},
     ops::{Deref, DerefMut},
+    panic::Location,
+};
+
+mod attach;
+mod capture;
+mod carrier;
+mod context;
+mod fingerprint;
+mod frame;
+mod id;
+mod metadata;
+mod recorder;
+#[cfg(feature = "serde")]
+mod remote;
+mod report;
+#[cfg(feature = "serde")]
+mod schema;
+mod snippet;
+mod stats;
+mod term;
+mod trace;
+
+pub use capture::{
+    capture_policy, sampling, set_capture_policy, set_sampling, CapturePolicy, SampleKey, Sampling,
+};
+pub use carrier::{register_has_backtrace, HasBacktrace};
+pub use context::Context;
+pub use fingerprint::Fingerprint;
+pub use frame::Frame;
+pub use id::ErrorId;
+pub use metadata::Metadata;
+pub use recorder::{
+    disable_flight_recorder, enable_flight_recorder, install_panic_hook, recent_errors,
+    recent_errors_on_this_thread, RecentError,
 };
+#[cfg(feature = "serde")]
+pub use remote::RemoteBacktraceError;
+pub use report::Report;
+#[cfg(feature = "serde")]
+pub use schema::{BacktraceRecord, ErrorRecord, FrameRecord, MetadataRecord};
+pub use stats::{disable_stats, enable_stats, stats_snapshot, ErrorStats, StatsSnapshot};
+pub use trace::Trace;
 
 pub struct BacktraceError<E: Error> {
     pub inner: E,
     pub backtrace: Box<Backtrace>,
+    details: Box<Details>,
+}
+
+/// Everything we record about an error beyond the error itself and its
+/// backtrace, shared by both wrapper types.
+struct Details {
+    id: ErrorId,
+    /// Where the error was wrapped. Recorded even when no backtrace is.
+    location: &'static Location<'static>,
+    /// The type of the wrapped error, which `DynBacktraceError` erases.
+    type_name: &'static str,
+    context: Vec<String>,
+    /// When and where the backtrace was captured, if the policy said to.
+    metadata: Option<Box<Metadata>>,
+    /// Values added with `attach` and `attach_printable`, oldest first.
+    attachments: Vec<attach::Attachment>,
+    /// Locations the error was returned through, added with [`Trace`].
+    trace: Vec<&'static Location<'static>>,
+    /// Set when sampling skipped capturing a backtrace that the capture
+    /// policy would otherwise have taken.
+    unsampled: bool,
+}
+
+impl Details {
+    fn new(location: &'static Location<'static>, type_name: &'static str) -> Self {
+        Details {
+            id: ErrorId::next(),
+            location,
+            type_name,
+            context: Vec::new(),
+            trace: Vec::new(),
+            attachments: Vec::new(),
+            metadata: None,
+            unsampled: false,
+        }
+    }
+}
+
+impl<E: Error> BacktraceError<E> {
+    /// Context messages added with [`Context`], innermost first.
+    pub fn context(&self) -> &[String] {
+        &self.details.context
+    }
+
+    /// Adds a context message to this wrapper, as [`Context::context`]
+    /// does for a `Result<T, DynBacktraceError>`.
+    ///
+    /// On a `Result<T, BacktraceError<E>>`, [`Context::context`] can only
+    /// nest a second wrapper, which `?` flattens one level of. Use this,
+    /// through `map_err`, to add context to a typed error any number of
+    /// times.
+    ///
+    /// ```
+    /// use backtrace_error::{BacktraceError, Context};
+    /// use std::num::ParseIntError;
+    ///
+    /// fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    ///     s.parse::<i32>()
+    ///         .context("parsing port")
+    ///         .map_err(|err| err.push_context("reading config"))
+    ///         .map_err(|err| err.push_context("starting up"))
+    /// }
+    ///
+    /// let err = parse("http").unwrap_err();
+    /// assert_eq!(err.context(), ["parsing port", "reading config", "starting up"]);
+    /// ```
+    pub fn push_context<C: Display>(mut self, context: C) -> Self {
+        self.details.context.push(context.to_string());
+        self
+    }
+
+    /// Locations this error was returned through, added with [`Trace`],
+    /// innermost first.
+    pub fn return_trace(&self) -> &[&'static Location<'static>] {
+        &self.details.trace
+    }
 }
 
-impl<E: Error> Display for BacktraceError<E> {
+impl<E: Error + 'static> BacktraceError<E> {
+    /// The source location where this error was first wrapped: the `?`,
+    /// `.into()`, [`BacktraceError::new`] or [`BtExt::bt`] call. This is
+    /// recorded even when no backtrace is, and printed in its place.
+    pub fn location(&self) -> &'static Location<'static> {
+        carrier::origin(&self.inner, &self.details).location
+    }
+
+    /// The process-unique ID of this error, printed at the top of its
+    /// report. Wrapping the error again keeps the same ID.
+    pub fn id(&self) -> ErrorId {
+        carrier::origin(&self.inner, &self.details).id
+    }
+
+    /// When and on which thread the backtrace was captured, or `None` if
+    /// none was.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        carrier::metadata(&self.inner, &self.details)
+    }
+
+    /// The frames of the backtrace this error carries (its own, or the one
+    /// it reused from the wrapped error), or an empty list if none was
+    /// captured.
+    pub fn frames(&self) -> Vec<Frame> {
+        Frame::from_backtrace(carrier::backtrace(&self.inner, &self.backtrace))
+    }
+}
+
+impl<E: Error + 'static> BacktraceError<E> {
+    /// The report printed by `Display`, with options for rendering it
+    /// differently.
+    pub fn report(&self) -> Report<'_> {
+        Report::new(&self.inner, &self.backtrace, &self.details)
+    }
+}
+
+impl<E: Error + 'static> Display for BacktraceError<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Initial error: {:}", self.inner)?;
-        writeln!(f, "Error context:")?;
-        writeln!(f, "{:}", self.backtrace)
+        self.report().fmt(f)
     }
 }
 
-impl<E: Error> Debug for BacktraceError<E> {
+impl<E: Error + 'static> Debug for BacktraceError<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         <Self as Display>::fmt(self, f)
     }
@@ -134,9 +332,74 @@ impl<E:Error + 'static> std::any::Provider for BacktraceError<E> {
 */
 
 impl<E: Error + 'static> From<E> for BacktraceError<E> {
+    #[track_caller]
     fn from(inner: E) -> Self {
-        let backtrace = Box::new(Backtrace::capture());
-        Self { inner, backtrace }
+        Self::capture_at(inner, Location::caller())
+    }
+}
+
+impl<E: Error + 'static> BacktraceError<E> {
+    /// Wraps `inner`, capturing a backtrace as `From` does. Convenient where
+    /// the target type of an `.into()` can't be inferred.
+    #[track_caller]
+    pub fn new(inner: E) -> Self {
+        Self::capture_at(inner, Location::caller())
+    }
+
+    pub(crate) fn capture_at(inner: E, location: &'static Location<'static>) -> Self {
+        carrier::register_wrapper::<E>();
+        let (backtrace, details) = capture::capture(&inner, location);
+        Self {
+            inner,
+            backtrace,
+            details,
+        }
+    }
+}
+
+/// Flattens the nesting produced by calling [`Context::context`] on a
+/// `Result<T, BacktraceError<E>>`, so that `?` gets back to the original
+/// type with the added context kept.
+impl<E: Error + 'static> From<BacktraceError<BacktraceError<E>>> for BacktraceError<E> {
+    fn from(outer: BacktraceError<BacktraceError<E>>) -> Self {
+        let mut inner = outer.inner;
+        inner.details.context.extend(outer.details.context);
+        inner.details.trace.extend(outer.details.trace);
+        inner.details.attachments.extend(outer.details.attachments);
+        inner
+    }
+}
+
+impl<E: Error + Send + Sync + 'static> BacktraceError<E> {
+    /// Erases the error type, moving the already-captured backtrace into
+    /// the resulting `DynBacktraceError` rather than capturing a new one.
+    /// Use this (for example via `.map_err(BacktraceError::into_dyn)`)
+    /// rather than `?` when a typed error crosses into code returning
+    /// `DynBacktraceError`: `?` also keeps the original backtrace, but wraps
+    /// the whole `BacktraceError<E>` so that `is::<E>()` and friends no
+    /// longer see `E` directly.
+    ///
+    /// ```
+    /// use backtrace_error::{BacktraceError, DynBacktraceError};
+    /// use std::num::ParseIntError;
+    ///
+    /// fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    ///     Ok(s.parse::<i32>()?)
+    /// }
+    ///
+    /// fn run() -> Result<i32, DynBacktraceError> {
+    ///     parse("nope").map_err(BacktraceError::into_dyn)
+    /// }
+    ///
+    /// let err = run().unwrap_err();
+    /// assert!(err.is::<ParseIntError>());
+    /// ```
+    pub fn into_dyn(self) -> DynBacktraceError {
+        DynBacktraceError {
+            inner: Box::new(self.inner),
+            backtrace: self.backtrace,
+            details: self.details,
+        }
     }
 }
 
@@ -148,32 +411,192 @@ pub trait ResultExt: Sized {
     fn expect_or_backtrace(self, msg: &str) -> Self::T;
 }
 
-impl<T, E: Error> ResultExt for Result<T, BacktraceError<E>> {
+impl<T, E: Error + 'static> ResultExt for Result<T, BacktraceError<E>> {
     type T = T;
     fn expect_or_backtrace(self, msg: &str) -> T {
         match self {
             Ok(ok) => ok,
             Err(bterr) => {
                 eprintln!("{}", msg);
-                eprintln!("");
-                eprintln!("{:}", bterr);
+                eprintln!();
+                eprintln!("{:}", bterr.report().stderr());
                 panic!("{}", msg);
             }
         }
     }
 }
 
+/// Wraps the error of a plain `Result` in a [`BacktraceError`], recording
+/// the location of the `.bt()` call.
+///
+/// This is what `?` does when the function returns a `BacktraceError<E>`,
+/// for the places where it doesn't: a `map_err` chain, a closure, or a value
+/// that is stored rather than returned.
+///
+/// ```
+/// use backtrace_error::BtExt;
+///
+/// let line = line!() + 1;
+/// let err = "x".parse::<i32>().bt().unwrap_err();
+/// assert_eq!(err.location().line(), line);
+/// ```
+pub trait BtExt<T, E: Error> {
+    fn bt(self) -> Result<T, BacktraceError<E>>;
+}
+
+impl<T, E: Error + 'static> BtExt<T, E> for Result<T, E> {
+    #[track_caller]
+    fn bt(self) -> Result<T, BacktraceError<E>> {
+        let location = Location::caller();
+        self.map_err(|err| BacktraceError::capture_at(err, location))
+    }
+}
+
 pub struct DynBacktraceError {
     inner: Box<dyn Error + Send + Sync + 'static>,
     backtrace: Box<Backtrace>,
+    details: Box<Details>,
 }
 
 impl<E: Error + Send + Sync + 'static> From<E> for DynBacktraceError {
+    #[track_caller]
     fn from(inner: E) -> Self {
-        let backtrace = Box::new(Backtrace::capture());
+        let (backtrace, details) = capture::capture(&inner, Location::caller());
         Self {
             inner: Box::new(inner),
             backtrace,
+            details,
+        }
+    }
+}
+
+impl DynBacktraceError {
+    /// Wraps `inner`, capturing a backtrace as `From` does.
+    #[track_caller]
+    pub fn new<E: Error + Send + Sync + 'static>(inner: E) -> Self {
+        Self::from(inner)
+    }
+
+    /// The source location where this error was first wrapped. This is
+    /// recorded even when no backtrace is, and printed in its place.
+    pub fn location(&self) -> &'static Location<'static> {
+        carrier::origin(&*self.inner, &self.details).location
+    }
+
+    /// The process-unique ID of this error, printed at the top of its
+    /// report. Wrapping the error again keeps the same ID.
+    pub fn id(&self) -> ErrorId {
+        carrier::origin(&*self.inner, &self.details).id
+    }
+
+    /// When and on which thread the backtrace was captured, or `None` if
+    /// none was.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        carrier::metadata(&*self.inner, &self.details)
+    }
+
+    /// The backtrace captured when this error was constructed, or the one
+    /// carried by the wrapped error if it already had one.
+    pub fn backtrace(&self) -> &Backtrace {
+        carrier::backtrace(&*self.inner, &self.backtrace)
+    }
+
+    /// The frames of [`Self::backtrace`], or an empty list if it was not
+    /// captured.
+    pub fn frames(&self) -> Vec<Frame> {
+        Frame::from_backtrace(self.backtrace())
+    }
+
+    /// Context messages added with [`Context`], innermost first.
+    pub fn context(&self) -> &[String] {
+        &self.details.context
+    }
+
+    /// Adds a context message to this wrapper. This is what
+    /// [`Context::context`] does on a `Result<T, DynBacktraceError>`.
+    pub fn push_context<C: Display>(mut self, context: C) -> Self {
+        self.details.context.push(context.to_string());
+        self
+    }
+
+    /// Locations this error was returned through, added with [`Trace`],
+    /// innermost first.
+    pub fn return_trace(&self) -> &[&'static Location<'static>] {
+        &self.details.trace
+    }
+
+    /// Discards the backtrace and returns the type-erased error.
+    pub fn into_inner(self) -> Box<dyn Error + Send + Sync + 'static> {
+        self.inner
+    }
+
+    /// Returns true if the wrapped error is of type `E`.
+    pub fn is<E: Error + 'static>(&self) -> bool {
+        self.inner.is::<E>()
+    }
+
+    /// Returns a reference to the wrapped error if it is of type `E`.
+    pub fn downcast_ref<E: Error + 'static>(&self) -> Option<&E> {
+        self.inner.downcast_ref::<E>()
+    }
+
+    /// Returns a mutable reference to the wrapped error if it is of type `E`.
+    pub fn downcast_mut<E: Error + 'static>(&mut self) -> Option<&mut E> {
+        self.inner.downcast_mut::<E>()
+    }
+
+    /// Attempts to recover the typed `BacktraceError<E>`, keeping the
+    /// original backtrace. On failure `self` is handed back unchanged.
+    ///
+    /// This is the inverse of [`BacktraceError::into_dyn`]. If the wrapped
+    /// error is itself a `BacktraceError<E>` (because it was converted with
+    /// `?` rather than `into_dyn`) that inner wrapper is returned, with the
+    /// backtrace from its original capture site.
+    ///
+    /// ```
+    /// use backtrace_error::DynBacktraceError;
+    /// use std::{fs, io};
+    ///
+    /// fn open_file() -> Result<fs::File, DynBacktraceError> {
+    ///     Ok(fs::File::open("/does-not-exist.nope")?)
+    /// }
+    ///
+    /// let err = open_file().unwrap_err();
+    /// assert!(err.is::<io::Error>());
+    /// let err = err.downcast::<std::num::ParseIntError>().unwrap_err();
+    /// let typed = err.downcast::<io::Error>().unwrap();
+    /// assert_eq!(typed.inner.kind(), io::ErrorKind::NotFound);
+    /// ```
+    pub fn downcast<E: Error + 'static>(self) -> Result<BacktraceError<E>, Self> {
+        let DynBacktraceError {
+            inner,
+            backtrace,
+            details,
+        } = self;
+        let inner = match inner.downcast::<E>() {
+            Ok(inner) => {
+                carrier::register_wrapper::<E>();
+                return Ok(BacktraceError {
+                    inner: *inner,
+                    backtrace,
+                    details,
+                });
+            }
+            Err(inner) => inner,
+        };
+        match inner.downcast::<BacktraceError<E>>() {
+            Ok(typed) => {
+                let mut typed = *typed;
+                typed.details.context.extend(details.context);
+                typed.details.trace.extend(details.trace);
+                typed.details.attachments.extend(details.attachments);
+                Ok(typed)
+            }
+            Err(inner) => Err(DynBacktraceError {
+                inner,
+                backtrace,
+                details,
+            }),
         }
     }
 }
@@ -191,11 +614,17 @@ impl DerefMut for DynBacktraceError {
     }
 }
 
+impl DynBacktraceError {
+    /// The report printed by `Display`, with options for rendering it
+    /// differently.
+    pub fn report(&self) -> Report<'_> {
+        Report::new(&*self.inner, &self.backtrace, &self.details)
+    }
+}
+
 impl Display for DynBacktraceError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Initial error: {:}", self.inner)?;
-        writeln!(f, "Error context:")?;
-        writeln!(f, "{:}", self.backtrace)
+        self.report().fmt(f)
     }
 }
 
@@ -205,15 +634,15 @@ impl Debug for DynBacktraceError {
     }
 }
 
-impl ResultExt for Result<(), DynBacktraceError> {
-    type T = ();
-    fn expect_or_backtrace(self, msg: &str) -> () {
+impl<T> ResultExt for Result<T, DynBacktraceError> {
+    type T = T;
+    fn expect_or_backtrace(self, msg: &str) -> T {
         match self {
-            Ok(()) => (),
+            Ok(ok) => ok,
             Err(bterr) => {
                 eprintln!("{}", msg);
-                eprintln!("");
-                eprintln!("{:}", bterr);
+                eprintln!();
+                eprintln!("{:}", bterr.report().stderr());
                 panic!("{}", msg);
             }
         }
@@ -0,0 +1,96 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! When, and on which thread, an error was captured.
+
+use std::{
+    fmt,
+    thread::{self, ThreadId},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Where and when a backtrace was captured.
+///
+/// Recorded alongside the backtrace whenever one is captured, so not when
+/// the [`CapturePolicy`] or [`Sampling`] skip it, and printed as the first
+/// line of the report.
+///
+/// [`CapturePolicy`]: crate::CapturePolicy
+/// [`Sampling`]: crate::Sampling
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// Wall-clock time of capture, for lining up with other logs.
+    pub time: SystemTime,
+    /// Monotonic time of capture, for measuring intervals within the process.
+    pub instant: Instant,
+    pub thread_name: Option<String>,
+    pub thread_id: ThreadId,
+    pub pid: u32,
+}
+
+impl Metadata {
+    pub(crate) fn capture() -> Metadata {
+        let thread = thread::current();
+        Metadata {
+            time: SystemTime::now(),
+            instant: Instant::now(),
+            thread_name: thread.name().map(String::from),
+            thread_id: thread.id(),
+            pid: std::process::id(),
+        }
+    }
+}
+
+/// Prints e.g. `2024-03-01T12:34:56.789Z on thread 'main' (ThreadId(1)) in
+/// process 4321`.
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Utc(self.time))?;
+        match &self.thread_name {
+            Some(name) => write!(f, " on thread '{}'", name)?,
+            None => f.write_str(" on unnamed thread")?,
+        }
+        write!(f, " ({:?}) in process {}", self.thread_id, self.pid)
+    }
+}
+
+/// Displays a time as an RFC 3339 UTC timestamp with millisecond precision.
+pub(crate) struct Utc(pub(crate) SystemTime);
+
+impl fmt::Display for Utc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let since_epoch = match self.0.duration_since(UNIX_EPOCH) {
+            Ok(since_epoch) => since_epoch,
+            Err(_) => return write!(f, "{:?}", self.0),
+        };
+        let secs = since_epoch.as_secs();
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let secs_of_day = secs % 86_400;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60,
+            since_epoch.subsec_millis()
+        )
+    }
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date, using
+/// Howard Hinnant's algorithm for the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,235 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! An opt-in flight recorder of recently wrapped errors, for finding the
+//! error that was swallowed a few calls before a panic.
+
+use crate::{frame, metadata::Utc, Details, ErrorId, Frame};
+use std::{
+    backtrace::Backtrace,
+    cell::RefCell,
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    panic::{self, Location},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+    thread::{self, ThreadId},
+    time::SystemTime,
+};
+
+/// One error kept by the flight recorder.
+#[derive(Clone, Debug)]
+pub struct RecentError {
+    pub id: ErrorId,
+    pub time: SystemTime,
+    pub thread_name: Option<String>,
+    pub thread_id: ThreadId,
+    pub type_name: &'static str,
+    pub message: String,
+    pub location: &'static Location<'static>,
+    /// The error's backtrace, if one was captured.
+    pub frames: Vec<Frame>,
+}
+
+// Zero means the recorder is off.
+static CAPACITY: AtomicUsize = AtomicUsize::new(0);
+// Bumped whenever the recorder is disabled, so that each thread can tell
+// its own history is stale without anyone else touching it.
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+static GLOBAL: Mutex<Option<VecDeque<RecentError>>> = Mutex::new(None);
+
+thread_local! {
+    static LOCAL: RefCell<(usize, VecDeque<RecentError>)> = const { RefCell::new((0, VecDeque::new())) };
+}
+
+/// Starts keeping the last `capacity` wrapped errors, both for each thread
+/// and across the whole process.
+///
+/// Each recorded error's backtrace is symbolized when it is recorded, so
+/// like [`enable_stats`] this is off by default. See also
+/// [`install_panic_hook`].
+///
+/// ```
+/// use backtrace_error::{enable_flight_recorder, recent_errors, DynBacktraceError};
+///
+/// enable_flight_recorder(8);
+/// let _swallowed = DynBacktraceError::from(std::fmt::Error);
+/// assert_eq!(recent_errors().len(), 1);
+/// ```
+///
+/// [`enable_stats`]: crate::enable_stats
+pub fn enable_flight_recorder(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+    let mut global = GLOBAL.lock().unwrap_or_else(PoisonError::into_inner);
+    truncate(global.get_or_insert_with(VecDeque::new), capacity);
+}
+
+/// Stops recording errors and discards everything recorded so far.
+pub fn disable_flight_recorder() {
+    CAPACITY.store(0, Ordering::Relaxed);
+    EPOCH.fetch_add(1, Ordering::Relaxed);
+    *GLOBAL.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// The most recently wrapped errors across all threads, oldest first.
+pub fn recent_errors() -> Vec<RecentError> {
+    let global = GLOBAL.lock().unwrap_or_else(PoisonError::into_inner);
+    global.iter().flatten().cloned().collect()
+}
+
+/// The most recently wrapped errors on the current thread, oldest first.
+pub fn recent_errors_on_this_thread() -> Vec<RecentError> {
+    LOCAL
+        .try_with(|local| {
+            with_local(&mut local.borrow_mut(), |errors| {
+                errors.iter().cloned().collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// Runs `f` on this thread's history, first discarding it if the recorder
+/// was disabled since it was last touched.
+fn with_local<T>(
+    local: &mut (usize, VecDeque<RecentError>),
+    f: impl FnOnce(&mut VecDeque<RecentError>) -> T,
+) -> T {
+    let epoch = EPOCH.load(Ordering::Relaxed);
+    if local.0 != epoch {
+        *local = (epoch, VecDeque::new());
+    }
+    f(&mut local.1)
+}
+
+fn truncate(errors: &mut VecDeque<RecentError>, capacity: usize) {
+    while errors.len() > capacity {
+        errors.pop_front();
+    }
+}
+
+fn push(errors: &mut VecDeque<RecentError>, error: RecentError, capacity: usize) {
+    errors.push_back(error);
+    truncate(errors, capacity);
+}
+
+/// Records a newly wrapped error, if the recorder is on.
+pub(crate) fn record(err: &dyn Error, backtrace: &Backtrace, details: &Details) {
+    let capacity = CAPACITY.load(Ordering::Relaxed);
+    if capacity == 0 {
+        return;
+    }
+    let (time, thread_name, thread_id) = match &details.metadata {
+        Some(metadata) => (
+            metadata.time,
+            metadata.thread_name.clone(),
+            metadata.thread_id,
+        ),
+        None => {
+            let thread = thread::current();
+            (
+                SystemTime::now(),
+                thread.name().map(String::from),
+                thread.id(),
+            )
+        }
+    };
+    let error = RecentError {
+        id: details.id,
+        time,
+        thread_name,
+        thread_id,
+        type_name: details.type_name,
+        message: err.to_string(),
+        location: details.location,
+        frames: Frame::from_backtrace(backtrace),
+    };
+    // The thread-local may already be gone if we're called from a
+    // destructor during thread exit; the global history still gets it.
+    let _ = LOCAL.try_with(|local| {
+        with_local(&mut local.borrow_mut(), |errors| {
+            push(errors, error.clone(), capacity)
+        })
+    });
+    let mut global = GLOBAL.lock().unwrap_or_else(PoisonError::into_inner);
+    push(global.get_or_insert_with(VecDeque::new), error, capacity);
+}
+
+/// Installs a panic hook that prints the flight recorder's history (this
+/// thread's errors, then other threads') before handing over to the
+/// previously installed hook, which prints the panic message as usual.
+/// Prints nothing extra if no errors were recorded.
+pub fn install_panic_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let history = History::current();
+        if !history.is_empty() {
+            eprint!("{}", history);
+        }
+        previous(info);
+    }));
+}
+
+struct History {
+    this_thread: Vec<RecentError>,
+    other_threads: Vec<RecentError>,
+}
+
+impl History {
+    fn current() -> History {
+        let this_thread = recent_errors_on_this_thread();
+        let id = thread::current().id();
+        let mut other_threads = recent_errors();
+        other_threads.retain(|error| error.thread_id != id);
+        History {
+            this_thread,
+            other_threads,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.this_thread.is_empty() && self.other_threads.is_empty()
+    }
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (title, errors) in [
+            ("this thread", &self.this_thread),
+            ("other threads", &self.other_threads),
+        ] {
+            if errors.is_empty() {
+                continue;
+            }
+            writeln!(f, "Recent errors on {} (oldest first):", title)?;
+            for error in errors {
+                write!(f, "{}", error)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints a header line for the error, then its backtrace (or location)
+/// indented below it.
+impl fmt::Display for RecentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "    {} Error {} on ", Utc(self.time), self.id)?;
+        match &self.thread_name {
+            Some(name) => write!(f, "thread '{}'", name)?,
+            None => write!(f, "unnamed thread ({:?})", self.thread_id)?,
+        }
+        writeln!(f, ": {}: {}", self.type_name, self.message)?;
+        let range = frame::interesting_range(&self.frames);
+        if range.is_empty() {
+            writeln!(f, "        at {}", self.location)?;
+        }
+        for frame in &self.frames[range] {
+            writeln!(f, "        {}", frame)?;
+        }
+        Ok(())
+    }
+}
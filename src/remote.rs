@@ -0,0 +1,104 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! Errors received from another process in the serialized form.
+
+use crate::{report, schema::ErrorRecord, Frame};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt};
+
+/// An error deserialized from an [`ErrorRecord`] that another process
+/// serialized, with the backtrace that was captured there.
+///
+/// Its `Display` impl prints the same report a local wrapper would, with
+/// the remote frames under "Remote backtrace:". It can also be the wrapped
+/// error, or a `source()`, of a local `DynBacktraceError`, whose report
+/// then shows the remote frames and, under "Local backtrace:", where the
+/// error surfaced in this process.
+///
+/// ```
+/// use backtrace_error::{DynBacktraceError, RemoteBacktraceError};
+///
+/// // In the worker:
+/// let json = serde_json::to_string(&DynBacktraceError::from(std::fmt::Error)).unwrap();
+///
+/// // In the coordinator:
+/// let remote: RemoteBacktraceError = serde_json::from_str(&json).unwrap();
+/// assert_eq!(remote.record().message, "an error occurred when formatting an argument");
+/// let err = DynBacktraceError::from(remote);
+/// assert!(err.to_string().contains("Initial error: an error occurred when formatting"));
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "ErrorRecord", into = "ErrorRecord")]
+pub struct RemoteBacktraceError {
+    record: ErrorRecord,
+    source: Option<Box<RemoteSource>>,
+}
+
+impl RemoteBacktraceError {
+    /// Everything that was received.
+    pub fn record(&self) -> &ErrorRecord {
+        &self.record
+    }
+
+    /// The remote backtrace, empty unless one was captured there.
+    pub fn frames(&self) -> Vec<Frame> {
+        self.record
+            .backtrace
+            .frames
+            .iter()
+            .map(Frame::from)
+            .collect()
+    }
+}
+
+impl From<ErrorRecord> for RemoteBacktraceError {
+    fn from(record: ErrorRecord) -> Self {
+        // The sources arrive as messages only; they are chained back up so
+        // that `source()` walks them as it did in the remote process.
+        let source = record.sources.iter().rev().fold(None, |next, message| {
+            Some(Box::new(RemoteSource {
+                message: message.clone(),
+                next,
+            }))
+        });
+        RemoteBacktraceError { record, source }
+    }
+}
+
+impl From<RemoteBacktraceError> for ErrorRecord {
+    fn from(remote: RemoteBacktraceError) -> Self {
+        remote.record
+    }
+}
+
+impl fmt::Display for RemoteBacktraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        report::write_remote_report(f, self)
+    }
+}
+
+impl Error for RemoteBacktraceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|s| s as &(dyn Error + 'static))
+    }
+}
+
+/// One message from a remote error's `source()` chain.
+#[derive(Clone, Debug)]
+struct RemoteSource {
+    message: String,
+    next: Option<Box<RemoteSource>>,
+}
+
+impl fmt::Display for RemoteSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for RemoteSource {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.next.as_deref().map(|s| s as &(dyn Error + 'static))
+    }
+}
@@ -0,0 +1,768 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! The multi-line report printed by the wrappers' `Display` impls, and the
+//! options for rendering it differently.
+
+use crate::{carrier, frame, metadata::Utc, snippet, term, Details, Frame, Metadata};
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    env,
+    error::Error,
+    fmt::{self, Display, Formatter, Write},
+    path::Path,
+    ptr,
+};
+
+/// A configurable rendering of a wrapper's report, returned by
+/// `BacktraceError::report` and `DynBacktraceError::report`. The wrappers'
+/// own `Display` impls print it with the default options.
+///
+/// ```
+/// use backtrace_error::DynBacktraceError;
+///
+/// let err = DynBacktraceError::from(std::fmt::Error);
+/// let plain = err.report().to_string();
+/// assert_eq!(plain, err.to_string());
+/// let colored = err.report().color(true).to_string();
+/// assert!(colored.contains("\x1b[1;31mInitial error:"));
+/// ```
+pub struct Report<'a> {
+    inner: &'a (dyn Error + 'static),
+    backtrace: &'a Backtrace,
+    details: &'a Details,
+    options: Options,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Options {
+    full: bool,
+    color: bool,
+    snippets: bool,
+    hyperlinks: bool,
+    format: Format,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum Format {
+    #[default]
+    Report,
+    Quickfix,
+    SingleLine,
+    Logfmt,
+}
+
+impl<'a> Report<'a> {
+    pub(crate) fn new(
+        inner: &'a (dyn Error + 'static),
+        backtrace: &'a Backtrace,
+        details: &'a Details,
+    ) -> Self {
+        Report {
+            inner,
+            backtrace,
+            details,
+            options: Options::default(),
+        }
+    }
+
+    /// Colors the report with ANSI escapes: the error in red, frames from
+    /// the current workspace in bold and others dimmed, locations
+    /// underlined.
+    pub fn color(mut self, on: bool) -> Self {
+        self.options.color = on;
+        self
+    }
+
+    /// Shows a few lines of source around each frame from the current
+    /// workspace, read from disk (once per file per process). Frames whose
+    /// file can't be read say so instead.
+    pub fn snippets(mut self, on: bool) -> Self {
+        self.options.snippets = on;
+        self
+    }
+
+    /// Makes each frame's location an OSC 8 terminal hyperlink to the
+    /// source file. The link is a `file://` URL with the line number as its
+    /// fragment, unless `BACKTRACE_ERROR_EDITOR` holds a URL template such
+    /// as `vscode://file/{path}:{line}:{column}`.
+    pub fn hyperlinks(mut self, on: bool) -> Self {
+        self.options.hyperlinks = on;
+        self
+    }
+
+    /// Switches to the compiler-style format that editors' quickfix lists
+    /// and problem matchers understand: one `path:line:col: error: message`
+    /// line at the first frame from the current workspace, then a
+    /// `path:line:col: note: ...` line for each further one. Without a
+    /// backtrace, the location the error was wrapped at is used instead.
+    ///
+    /// ```
+    /// use backtrace_error::DynBacktraceError;
+    ///
+    /// let line = line!() + 1;
+    /// let err = DynBacktraceError::from(std::fmt::Error);
+    /// let quickfix = err.report().quickfix().to_string();
+    /// let first = quickfix.lines().next().unwrap();
+    /// assert!(first.starts_with(&format!("{}:{}:", file!(), line)));
+    /// assert!(first.ends_with(": error: an error occurred when formatting an argument"));
+    /// ```
+    pub fn quickfix(mut self) -> Self {
+        self.options.format = Format::Quickfix;
+        self
+    }
+
+    /// Switches to a one-line format for line-oriented log shippers: the
+    /// error, its causes and any context separated by `: `, then the frames
+    /// joined by ` | `. Newlines inside messages are escaped as `\n`, and
+    /// `|` as `\|` so that it can't be mistaken for a separator.
+    ///
+    /// ```
+    /// use backtrace_error::DynBacktraceError;
+    ///
+    /// let err = DynBacktraceError::from(std::fmt::Error);
+    /// let line = err.report().single_line().to_string();
+    /// assert!(!line.contains('\n'));
+    /// assert!(line.starts_with("an error occurred when formatting an argument | "));
+    /// ```
+    pub fn single_line(mut self) -> Self {
+        self.options.format = Format::SingleLine;
+        self
+    }
+
+    /// Switches to [logfmt], one line of `key=value` pairs: `error`, `type`,
+    /// `id`, `location`, `time` (if captured), then `cause0`, `cause1`, ...,
+    /// `context0`, ... and `frame0`, `frame1`, ... as there are any. Values
+    /// are quoted when they need to be.
+    ///
+    /// [logfmt]: https://brandur.org/logfmt
+    ///
+    /// ```
+    /// use backtrace_error::DynBacktraceError;
+    ///
+    /// let err = DynBacktraceError::from(std::fmt::Error);
+    /// let line = err.report().logfmt().to_string();
+    /// assert!(line.starts_with(
+    ///     "error=\"an error occurred when formatting an argument\" type=core::fmt::Error "
+    /// ));
+    /// ```
+    pub fn logfmt(mut self) -> Self {
+        self.options.format = Format::Logfmt;
+        self
+    }
+
+    /// Picks the options suited to printing the report on stderr, as
+    /// `expect_or_backtrace` does: color if stderr is a terminal, unless
+    /// `NO_COLOR` or `CLICOLOR_FORCE` say otherwise, and hyperlinks if
+    /// stderr is a terminal and `BACKTRACE_ERROR_HYPERLINKS=1` or
+    /// `BACKTRACE_ERROR_EDITOR` is set. `BACKTRACE_ERROR_FORMAT=quickfix`
+    /// selects the [`quickfix`](Report::quickfix) format.
+    pub fn stderr(self) -> Self {
+        let report = self
+            .color(term::stderr_color())
+            .hyperlinks(term::stderr_hyperlinks());
+        match env::var("BACKTRACE_ERROR_FORMAT").as_deref() {
+            Ok("quickfix") => report.quickfix(),
+            _ => report,
+        }
+    }
+}
+
+impl Display for Report<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut options = self.options;
+        options.full = f.alternate() || full_from_env();
+        match options.format {
+            Format::Report => write_report(f, self.inner, self.backtrace, self.details, options),
+            Format::Quickfix => write_quickfix(f, self.inner, self.backtrace, self.details),
+            Format::SingleLine => {
+                write_single_line(f, self.inner, self.backtrace, self.details, options)
+            }
+            Format::Logfmt => write_logfmt(f, self.inner, self.backtrace, self.details, options),
+        }
+    }
+}
+
+/// What a report is about once carried backtraces are followed inward: the
+/// innermost wrapped error, the backtrace to print for it, and every
+/// wrapper's details, innermost first.
+pub(crate) struct Subject<'a> {
+    pub(crate) inner: &'a (dyn Error + 'static),
+    pub(crate) backtrace: &'a Backtrace,
+    pub(crate) unsampled: bool,
+    pub(crate) wrappers: Vec<&'a Details>,
+}
+
+pub(crate) fn subject<'a>(
+    inner: &'a (dyn Error + 'static),
+    backtrace: &'a Backtrace,
+    details: &'a Details,
+) -> Subject<'a> {
+    // If the wrapped error carries a backtrace we didn't capture one, so use
+    // the carried one instead (and, for our own wrappers, just the message
+    // they wrap rather than their whole report).
+    let layers = carrier::layers(inner);
+    let (inner, backtrace, unsampled) = match layers.last() {
+        Some(carried) => (
+            carried.error,
+            carried.backtrace,
+            carried.details.is_some_and(|d| d.unsampled),
+        ),
+        None => (inner, backtrace, details.unsampled),
+    };
+    let wrappers = layers
+        .iter()
+        .rev()
+        .filter_map(|carried| carried.details)
+        .chain(Some(details))
+        .collect();
+    Subject {
+        inner,
+        backtrace,
+        unsampled,
+        wrappers,
+    }
+}
+
+/// The metadata recorded with the backtrace, by the innermost of `wrappers`
+/// that captured one.
+pub(crate) fn metadata<'a>(wrappers: &[&'a Details]) -> Option<&'a Metadata> {
+    wrappers
+        .iter()
+        .find_map(|details| details.metadata.as_deref())
+}
+
+fn write_report(
+    f: &mut Formatter<'_>,
+    inner: &(dyn Error + 'static),
+    backtrace: &Backtrace,
+    details: &Details,
+    options: Options,
+) -> fmt::Result {
+    // Context pushed onto any of the wrappers along the way is printed
+    // innermost first.
+    let Subject {
+        inner,
+        backtrace,
+        unsampled,
+        wrappers,
+    } = subject(inner, backtrace, details);
+    let origin = wrappers[0];
+    write!(f, "Error {}", origin.id)?;
+    match metadata(&wrappers) {
+        Some(metadata) => writeln!(f, ", captured {}", metadata)?,
+        None => writeln!(f)?,
+    }
+    // An error from another process brings its own context and backtrace,
+    // which come before ours.
+    let remote = remote(inner);
+    term::paint(
+        f,
+        options.color,
+        term::RED,
+        &format_args!("Initial error: {}", message(inner)),
+    )?;
+    writeln!(f)?;
+    write_sources(f, inner, backtrace, options)?;
+    let remote_context = remote.iter().flat_map(|remote| remote.context);
+    let context = remote_context.chain(wrappers.iter().flat_map(|d| &d.context));
+    for (i, msg) in context.enumerate() {
+        if i == 0 {
+            writeln!(f, "Context:")?;
+        }
+        writeln!(f, "    {:}", msg)?;
+    }
+    let attachments = wrappers
+        .iter()
+        .flat_map(|d| &d.attachments)
+        .filter(|attachment| attachment.is_printable());
+    for (i, attachment) in attachments.enumerate() {
+        if i == 0 {
+            writeln!(f, "Attachments:")?;
+        }
+        writeln!(f, "    {:}", attachment)?;
+    }
+    if let Some(remote) = &remote {
+        writeln!(f, "Remote backtrace:")?;
+        write_remote_backtrace(f, remote, options)?;
+    }
+    if remote.is_some() || causes(inner).any(|source| self::remote(source).is_some()) {
+        writeln!(f, "Local backtrace:")?;
+    } else {
+        writeln!(f, "Error context:")?;
+    }
+    // Without a backtrace, the location we wrapped the error at is the best
+    // clue to where it came from.
+    if unsampled {
+        writeln!(f, "    at {} (backtrace not sampled)", origin.location)?;
+    } else if backtrace.status() != BacktraceStatus::Captured {
+        writeln!(f, "    at {} ({})", origin.location, backtrace)?;
+    } else {
+        write_backtrace(f, backtrace, options)?;
+    }
+    let trace = wrappers.iter().flat_map(|d| &d.trace);
+    for (i, location) in trace.enumerate() {
+        if i == 0 {
+            writeln!(f, "Propagated through:")?;
+        }
+        writeln!(f, "    at {}", location)?;
+    }
+    Ok(())
+}
+
+/// Writes the quickfix format described at [`Report::quickfix`]. Messages
+/// are kept to one line so that every line parses as a location.
+fn write_quickfix(
+    f: &mut Formatter<'_>,
+    inner: &(dyn Error + 'static),
+    backtrace: &Backtrace,
+    details: &Details,
+) -> fmt::Result {
+    let Subject {
+        inner,
+        backtrace,
+        wrappers,
+        ..
+    } = subject(inner, backtrace, details);
+    let error = one_line(&message(inner));
+    let frames = Frame::from_backtrace(backtrace);
+    let cwd = env::current_dir().ok();
+    let mut user_frames = frames[frame::interesting_range(&frames)]
+        .iter()
+        .filter_map(|frame| {
+            let rel = Path::new(frame.file.as_ref()?)
+                .strip_prefix(cwd.as_ref()?)
+                .ok()?;
+            Some((rel, frame.line?, frame.column.unwrap_or(1), frame))
+        });
+    match user_frames.next() {
+        Some((rel, line, column, _)) => {
+            writeln!(f, "{}:{}:{}: error: {}", rel.display(), line, column, error)?
+        }
+        None => writeln!(f, "{}: error: {}", wrappers[0].location, error)?,
+    }
+    for (rel, line, column, frame) in user_frames {
+        let symbol = frame.symbol.as_deref().unwrap_or("<unknown>");
+        writeln!(
+            f,
+            "{}:{}:{}: note: called from {}",
+            rel.display(),
+            line,
+            column,
+            symbol
+        )?;
+    }
+    for source in causes(inner) {
+        writeln!(
+            f,
+            "{}: note: caused by: {}",
+            wrappers[0].location,
+            one_line(&message(source))
+        )?;
+    }
+    for msg in wrappers.iter().flat_map(|d| &d.context) {
+        writeln!(f, "{}: note: {}", wrappers[0].location, one_line(msg))?;
+    }
+    Ok(())
+}
+
+/// Writes the format described at [`Report::single_line`].
+fn write_single_line(
+    f: &mut Formatter<'_>,
+    inner: &(dyn Error + 'static),
+    backtrace: &Backtrace,
+    details: &Details,
+    options: Options,
+) -> fmt::Result {
+    let subject = subject(inner, backtrace, details);
+    write!(f, "{}", Escaped(&message(subject.inner)))?;
+    for cause in causes(subject.inner) {
+        write!(f, ": {}", Escaped(&message(cause)))?;
+    }
+    for msg in subject.wrappers.iter().flat_map(|d| &d.context) {
+        write!(f, ": {}", Escaped(msg))?;
+    }
+    for frame in frame_summaries(&subject, options) {
+        write!(f, " | {}", Escaped(&frame))?;
+    }
+    Ok(())
+}
+
+/// Writes the format described at [`Report::logfmt`].
+fn write_logfmt(
+    f: &mut Formatter<'_>,
+    inner: &(dyn Error + 'static),
+    backtrace: &Backtrace,
+    details: &Details,
+    options: Options,
+) -> fmt::Result {
+    let subject = subject(inner, backtrace, details);
+    let origin = subject.wrappers[0];
+    write!(f, "error={}", Value(&message(subject.inner)))?;
+    write!(f, " type={}", Value(origin.type_name))?;
+    write!(f, " id={}", origin.id)?;
+    write!(f, " location={}", Value(&origin.location.to_string()))?;
+    if let Some(metadata) = metadata(&subject.wrappers) {
+        write!(f, " time={}", Utc(metadata.time))?;
+    }
+    for (i, cause) in causes(subject.inner).enumerate() {
+        write!(f, " cause{}={}", i, Value(&message(cause)))?;
+    }
+    let context = subject.wrappers.iter().flat_map(|d| &d.context);
+    for (i, msg) in context.enumerate() {
+        write!(f, " context{}={}", i, Value(msg))?;
+    }
+    for (i, frame) in frame_summaries(&subject, options).iter().enumerate() {
+        write!(f, " frame{}={}", i, Value(frame))?;
+    }
+    Ok(())
+}
+
+/// The `source()` chain below `err`, seen through any carriers and stopping
+/// at a cycle or after [`MAX_SOURCES`].
+pub(crate) fn causes<'a>(
+    err: &'a (dyn Error + 'static),
+) -> impl Iterator<Item = &'a (dyn Error + 'static)> {
+    let mut seen: Vec<*const (dyn Error + 'static)> = vec![err];
+    let mut next = err.source();
+    std::iter::from_fn(move || {
+        let source = next?;
+        let source = carrier::find(source).map_or(source, |carried| carried.error);
+        if seen_before(&seen, source) || seen.len() > MAX_SOURCES {
+            return None;
+        }
+        seen.push(source);
+        next = source.source();
+        Some(source)
+    })
+}
+
+/// Each frame as `symbol at path:line:col`, for the one-line formats. Frames
+/// are trimmed and paths shortened as in the full report. Without a
+/// backtrace there is just the location the error was wrapped at.
+fn frame_summaries(subject: &Subject<'_>, options: Options) -> Vec<String> {
+    let origin = subject.wrappers[0];
+    if subject.backtrace.status() != BacktraceStatus::Captured {
+        return vec![format!("at {}", origin.location)];
+    }
+    let frames = Frame::from_backtrace(subject.backtrace);
+    let range = if options.full {
+        0..frames.len()
+    } else {
+        frame::interesting_range(&frames)
+    };
+    let cwd = env::current_dir().ok();
+    frames[range]
+        .iter()
+        .map(|frame| {
+            let symbol = frame.symbol.as_deref().unwrap_or("<unknown>");
+            let file = match (&cwd, &frame.file) {
+                (Some(cwd), Some(file)) if !options.full => {
+                    match Path::new(file).strip_prefix(cwd) {
+                        Ok(rel) => format!(".{}{}", std::path::MAIN_SEPARATOR, rel.display()),
+                        Err(_) => file.clone(),
+                    }
+                }
+                (_, Some(file)) => file.clone(),
+                (_, None) => return symbol.to_string(),
+            };
+            match (frame.line, frame.column) {
+                (Some(line), Some(column)) => format!("{} at {}:{}:{}", symbol, file, line, column),
+                _ => format!("{} at {}", symbol, file),
+            }
+        })
+        .collect()
+}
+
+/// Displays text with backslashes, line breaks and `|` escaped, so that it
+/// stays on one line and can be split back apart at the separators.
+struct Escaped<'a>(&'a str);
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                '|' => f.write_str("\\|")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Displays a logfmt value, quoted (with quotes escaped) unless it is a
+/// single bare word.
+struct Value<'a>(&'a str);
+
+impl Display for Value<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let bare = !self.0.is_empty()
+            && !self
+                .0
+                .chars()
+                .any(|c| c == '"' || c == '=' || c.is_whitespace() || c.is_control());
+        if bare {
+            return f.write_str(self.0);
+        }
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+fn one_line(msg: &str) -> String {
+    msg.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Whether the environment asks for full backtraces, the same way it does
+/// for std's panic messages.
+fn full_from_env() -> bool {
+    env::var("RUST_LIB_BACKTRACE")
+        .or_else(|_| env::var("RUST_BACKTRACE"))
+        .is_ok_and(|v| v == "full")
+}
+
+/// Writes a backtrace in the same layout std uses. Unless `full` is set,
+/// frames belonging to the capture machinery and the runtime's startup code
+/// are left out (and counted), and paths under the current directory are
+/// shortened.
+fn write_backtrace(f: &mut dyn Write, backtrace: &Backtrace, options: Options) -> fmt::Result {
+    let frames = Frame::from_backtrace(backtrace);
+    if frames.is_empty() {
+        return writeln!(f, "{:}", backtrace);
+    }
+    write_frames(f, &frames, options)
+}
+
+fn write_frames(f: &mut dyn Write, frames: &[Frame], options: Options) -> fmt::Result {
+    let range = if options.full {
+        0..frames.len()
+    } else {
+        frame::interesting_range(frames)
+    };
+    let cwd = env::current_dir().ok();
+    let hidden = frames.len() - range.len();
+    for (i, frame) in frames.iter().enumerate().take(range.end).skip(range.start) {
+        let rel = match (&cwd, &frame.file) {
+            (Some(cwd), Some(file)) => Path::new(file).strip_prefix(cwd).ok(),
+            _ => None,
+        };
+        // Frames from files in the workspace are the user's own; the rest
+        // (std, dependencies) are dimmed.
+        let style = if rel.is_some() { term::BOLD } else { term::DIM };
+        write!(f, "{:4}: ", i)?;
+        let symbol = frame.symbol.as_deref().unwrap_or("<unknown>");
+        term::paint(f, options.color, style, &symbol)?;
+        writeln!(f)?;
+        if let Some(file_path) = &frame.file {
+            let file = match rel {
+                Some(rel) if !options.full => {
+                    format!(".{}{}", std::path::MAIN_SEPARATOR, rel.display())
+                }
+                _ => file_path.clone(),
+            };
+            let location = match (frame.line, frame.column) {
+                (Some(line), Some(column)) => format!("{}:{}:{}", file, line, column),
+                _ => file,
+            };
+            write!(f, "             at ")?;
+            if options.hyperlinks {
+                let url = term::link_url(Path::new(file_path), frame.line, frame.column);
+                let mut text = String::new();
+                term::paint(&mut text, options.color, term::UNDERLINE, &location)?;
+                term::hyperlink(f, &url, &text)?;
+            } else {
+                term::paint(f, options.color, term::UNDERLINE, &location)?;
+            }
+            writeln!(f)?;
+            if let (true, Some(_), Some(line)) = (options.snippets, rel, frame.line) {
+                snippet::write_snippet(
+                    f,
+                    "               ",
+                    file_path,
+                    line,
+                    frame.column,
+                    options.color,
+                )?;
+            }
+        }
+    }
+    if hidden > 0 {
+        let note = format!(
+            "      [{} frames hidden; format with {{:#}} or set RUST_BACKTRACE=full to show them]",
+            hidden
+        );
+        term::paint(f, options.color, term::DIM, &note)?;
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+/// Writes the `source()` chain below `err`, one numbered line per cause. A
+/// cause that is itself one of our wrappers (or a registered carrier) is
+/// printed as the error it wraps, followed by its backtrace unless that is
+/// one we have already printed or will print.
+fn write_sources(
+    f: &mut Formatter<'_>,
+    err: &(dyn Error + 'static),
+    backtrace: &Backtrace,
+    options: Options,
+) -> fmt::Result {
+    let mut seen: Vec<*const (dyn Error + 'static)> = vec![err];
+    let mut printed = vec![backtrace as *const Backtrace];
+    let mut next = err.source();
+    let mut n = 0;
+    while let Some(source) = next {
+        n += 1;
+        if n == 1 {
+            writeln!(f, "Caused by:")?;
+        }
+        let (source, carried) = match carrier::find(source) {
+            Some(carried) => (carried.error, Some(carried.backtrace)),
+            None => (source, None),
+        };
+        if seen_before(&seen, source) {
+            writeln!(f, "    {:}. <cycle in error sources>", n)?;
+            break;
+        }
+        if n > MAX_SOURCES {
+            writeln!(f, "    {:}. <further sources omitted>", n)?;
+            break;
+        }
+        seen.push(source);
+        writeln!(f, "    {:}. {:}", n, message(source))?;
+        if let Some(remote) = remote(source) {
+            writeln!(f, "       Remote backtrace:")?;
+            let mut text = String::new();
+            write_remote_backtrace(&mut text, &remote, options)?;
+            for line in text.lines() {
+                writeln!(f, "       {:}", line)?;
+            }
+        }
+        if let Some(bt) = carried {
+            let ptr = bt as *const Backtrace;
+            if bt.status() == BacktraceStatus::Captured && !printed.contains(&ptr) {
+                printed.push(ptr);
+                writeln!(f, "       Error context:")?;
+                let mut text = String::new();
+                write_backtrace(&mut text, bt, options)?;
+                for line in text.lines() {
+                    writeln!(f, "       {:}", line)?;
+                }
+            }
+        }
+        next = source.source();
+    }
+    Ok(())
+}
+
+/// What a report shows of a `RemoteBacktraceError`.
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+struct Remote<'a> {
+    message: &'a str,
+    context: &'a [String],
+    frames: Vec<Frame>,
+    status: &'a str,
+    location: &'a str,
+}
+
+/// Looks at `err` as a `RemoteBacktraceError`, if it is one.
+fn remote<'a>(err: &'a (dyn Error + 'static)) -> Option<Remote<'a>> {
+    #[cfg(feature = "serde")]
+    if let Some(remote) = err.downcast_ref::<crate::RemoteBacktraceError>() {
+        let record = remote.record();
+        return Some(Remote {
+            message: &record.message,
+            context: &record.context,
+            frames: remote.frames(),
+            status: &record.backtrace.status,
+            location: &record.location,
+        });
+    }
+    let _ = err;
+    None
+}
+
+/// An error's message. A `RemoteBacktraceError` displays as a whole report,
+/// so for one of those this is only the message it carries.
+pub(crate) fn message(err: &(dyn Error + 'static)) -> String {
+    match remote(err) {
+        Some(remote) => remote.message.to_string(),
+        None => err.to_string(),
+    }
+}
+
+/// Writes a remote backtrace as if it had been captured here, or, if none
+/// was, where the remote error was wrapped.
+fn write_remote_backtrace(f: &mut dyn Write, remote: &Remote<'_>, options: Options) -> fmt::Result {
+    if !remote.frames.is_empty() {
+        return write_frames(f, &remote.frames, options);
+    }
+    let location = if remote.location.is_empty() {
+        "<unknown>"
+    } else {
+        remote.location
+    };
+    match remote.status {
+        "unsampled" => writeln!(f, "    at {} (backtrace not sampled)", location),
+        status => writeln!(f, "    at {} ({} backtrace)", location, status),
+    }
+}
+
+/// Writes the report printed by `RemoteBacktraceError`'s `Display` impl:
+/// the local report's layout, with the remote backtrace in place of ours.
+#[cfg(feature = "serde")]
+pub(crate) fn write_remote_report(
+    f: &mut Formatter<'_>,
+    err: &crate::RemoteBacktraceError,
+) -> fmt::Result {
+    let options = Options {
+        full: f.alternate() || full_from_env(),
+        ..Options::default()
+    };
+    let record = err.record();
+    match (record.id.as_str(), &record.metadata) {
+        ("", _) => writeln!(f, "Remote error")?,
+        (id, Some(metadata)) => writeln!(f, "Error {}, captured {}", id, metadata)?,
+        (id, None) => writeln!(f, "Error {}", id)?,
+    }
+    writeln!(f, "Initial error: {}", record.message)?;
+    write_sources(f, err, &Backtrace::disabled(), options)?;
+    for (i, msg) in record.context.iter().enumerate() {
+        if i == 0 {
+            writeln!(f, "Context:")?;
+        }
+        writeln!(f, "    {:}", msg)?;
+    }
+    writeln!(f, "Remote backtrace:")?;
+    match remote(err) {
+        Some(remote) => write_remote_backtrace(f, &remote, options),
+        None => Ok(()),
+    }
+}
+
+/// How many sources a report follows at most, in case a cycle goes
+/// unnoticed by [`seen_before`].
+const MAX_SOURCES: usize = 64;
+
+/// Whether `err` is one already visited. The address alone can't tell: a
+/// newtype's first field has the same address (and, if the newtype adds
+/// nothing, the same size) as the newtype itself. So the vtable is compared
+/// too. Vtables need not be unique, so a cycle could be noticed a lap late,
+/// or not at all, which `MAX_SOURCES` covers.
+fn seen_before(seen: &[*const (dyn Error + 'static)], err: &(dyn Error + 'static)) -> bool {
+    seen.iter().any(|&other| ptr::eq(other, err))
+}
@@ -0,0 +1,211 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! The serialized form of the wrappers, behind the `serde` feature.
+//!
+//! Both wrappers serialize as an [`ErrorRecord`], whose fields are the
+//! schema. It is stable: fields may be added in later versions, but none
+//! will be removed or change meaning, and the ones that may be missing
+//! deserialize to empty values.
+
+use crate::{
+    metadata::Utc,
+    report::{self, Subject},
+    BacktraceError, Details, DynBacktraceError, Frame,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    error::Error,
+    fmt,
+};
+
+/// A wrapped error, its backtrace and what else was recorded with it, as
+/// plain data. This is what `BacktraceError` and `DynBacktraceError`
+/// serialize as, and what to deserialize them into.
+///
+/// In JSON:
+///
+/// ```json
+/// {
+///   "type": "std::io::error::Error",
+///   "message": "reading config failed",
+///   "sources": ["No such file or directory (os error 2)"],
+///   "backtrace": {
+///     "status": "captured",
+///     "frames": [
+///       {"symbol": "app::load", "file": "src/main.rs", "line": 12, "col": 5},
+///       {"symbol": "main", "file": "src/main.rs", "line": 30, "col": 9}
+///     ]
+///   },
+///   "context": ["starting up"],
+///   "metadata": {
+///     "time": "2024-03-01T12:34:56.789Z",
+///     "thread_name": "main",
+///     "thread_id": "ThreadId(1)",
+///     "pid": 4321
+///   },
+///   "id": "000010e1-1",
+///   "location": "src/main.rs:12:5"
+/// }
+/// ```
+///
+/// `type`, `id`, `location` and `metadata` describe where the error was
+/// first wrapped, as the report header does; `context` is every wrapper's,
+/// innermost first. `metadata` is `null` when no backtrace was captured.
+///
+/// ```
+/// use backtrace_error::{DynBacktraceError, ErrorRecord};
+///
+/// let err = DynBacktraceError::from(std::fmt::Error);
+/// let json = serde_json::to_string(&err).unwrap();
+/// let record: ErrorRecord = serde_json::from_str(&json).unwrap();
+/// assert_eq!(record, ErrorRecord::from(&err));
+/// assert_eq!(record.type_name, "core::fmt::Error");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorRecord {
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub message: String,
+    /// The messages of the `source()` chain, outermost first.
+    #[serde(default)]
+    pub sources: Vec<String>,
+    pub backtrace: BacktraceRecord,
+    #[serde(default)]
+    pub context: Vec<String>,
+    #[serde(default)]
+    pub metadata: Option<MetadataRecord>,
+    #[serde(default)]
+    pub id: String,
+    /// Where the error was wrapped, as `file:line:col`.
+    #[serde(default)]
+    pub location: String,
+}
+
+/// A backtrace in an [`ErrorRecord`]. `status` is `captured`, `disabled`,
+/// `unsupported` or `unsampled` (the policy wanted a backtrace but sampling
+/// skipped it). All frames are kept, including the ones reports hide.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BacktraceRecord {
+    pub status: String,
+    #[serde(default)]
+    pub frames: Vec<FrameRecord>,
+}
+
+/// One frame in a [`BacktraceRecord`], with whatever std resolved of it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameRecord {
+    pub symbol: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub col: Option<u32>,
+}
+
+/// The [`Metadata`](crate::Metadata) in an [`ErrorRecord`]. `time` is an
+/// RFC 3339 UTC timestamp and `thread_id` is the `Debug` form of the
+/// `ThreadId`, which is all std offers.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataRecord {
+    pub time: String,
+    pub thread_name: Option<String>,
+    pub thread_id: String,
+    pub pid: u32,
+}
+
+/// Prints the same as [`Metadata`](crate::Metadata) does.
+impl fmt::Display for MetadataRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.time)?;
+        match &self.thread_name {
+            Some(name) => write!(f, " on thread '{}'", name)?,
+            None => f.write_str(" on unnamed thread")?,
+        }
+        write!(f, " ({}) in process {}", self.thread_id, self.pid)
+    }
+}
+
+impl From<&Frame> for FrameRecord {
+    fn from(frame: &Frame) -> Self {
+        FrameRecord {
+            symbol: frame.symbol.clone(),
+            file: frame.file.clone(),
+            line: frame.line,
+            col: frame.column,
+        }
+    }
+}
+
+impl From<&FrameRecord> for Frame {
+    fn from(frame: &FrameRecord) -> Self {
+        Frame {
+            symbol: frame.symbol.clone(),
+            file: frame.file.clone(),
+            line: frame.line,
+            column: frame.col,
+            ip: None,
+            is_inlined: false,
+        }
+    }
+}
+
+fn record(inner: &(dyn Error + 'static), backtrace: &Backtrace, details: &Details) -> ErrorRecord {
+    let Subject {
+        inner,
+        backtrace,
+        unsampled,
+        wrappers,
+    } = report::subject(inner, backtrace, details);
+    let origin = wrappers[0];
+    let status = match backtrace.status() {
+        _ if unsampled => "unsampled",
+        BacktraceStatus::Captured => "captured",
+        BacktraceStatus::Unsupported => "unsupported",
+        _ => "disabled",
+    };
+    ErrorRecord {
+        type_name: origin.type_name.to_string(),
+        message: report::message(inner),
+        sources: report::causes(inner).map(report::message).collect(),
+        backtrace: BacktraceRecord {
+            status: status.to_string(),
+            frames: Frame::from_backtrace(backtrace)
+                .iter()
+                .map(FrameRecord::from)
+                .collect(),
+        },
+        context: wrappers.iter().flat_map(|d| d.context.clone()).collect(),
+        metadata: report::metadata(&wrappers).map(|metadata| MetadataRecord {
+            time: Utc(metadata.time).to_string(),
+            thread_name: metadata.thread_name.clone(),
+            thread_id: format!("{:?}", metadata.thread_id),
+            pid: metadata.pid,
+        }),
+        id: origin.id.to_string(),
+        location: origin.location.to_string(),
+    }
+}
+
+impl<E: Error + 'static> From<&BacktraceError<E>> for ErrorRecord {
+    fn from(err: &BacktraceError<E>) -> Self {
+        record(&err.inner, &err.backtrace, &err.details)
+    }
+}
+
+impl From<&DynBacktraceError> for ErrorRecord {
+    fn from(err: &DynBacktraceError) -> Self {
+        record(&*err.inner, &err.backtrace, &err.details)
+    }
+}
+
+impl<E: Error + 'static> Serialize for BacktraceError<E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorRecord::from(self).serialize(serializer)
+    }
+}
+
+impl Serialize for DynBacktraceError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorRecord::from(self).serialize(serializer)
+    }
+}
@@ -0,0 +1,72 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! Source snippets shown under frames, read from disk once per file.
+
+use crate::term;
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+    fs,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// How many lines to show either side of the frame's line.
+const CONTEXT: u32 = 2;
+
+/// Each file's lines, or `None` if it couldn't be read. Only files named by
+/// workspace frames are ever read, so this stays small.
+type Cache = HashMap<String, Option<Arc<Vec<String>>>>;
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+fn lines(path: &str) -> Option<Arc<Vec<String>>> {
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    cache
+        .get_or_insert_with(HashMap::new)
+        .entry(path.to_string())
+        .or_insert_with(|| {
+            let text = fs::read_to_string(path).ok()?;
+            Some(Arc::new(text.lines().map(String::from).collect()))
+        })
+        .clone()
+}
+
+/// Writes the lines around `line` of the file at `path`, indented by
+/// `indent` and with `line` itself marked (and `column` pointed at, if
+/// known), or a note saying the source isn't available.
+pub(crate) fn write_snippet(
+    f: &mut dyn Write,
+    indent: &str,
+    path: &str,
+    line: u32,
+    column: Option<u32>,
+    color: bool,
+) -> fmt::Result {
+    let lines = match lines(path) {
+        Some(lines) if (line as usize) <= lines.len() && line > 0 => lines,
+        Some(_) => return writeln!(f, "{}(line {} is past the end of the file)", indent, line),
+        None => return writeln!(f, "{}(source not available)", indent),
+    };
+    let first = line.saturating_sub(CONTEXT).max(1);
+    let last = (line + CONTEXT).min(lines.len() as u32);
+    let width = last.to_string().len();
+    for n in first..=last {
+        let text = &lines[n as usize - 1];
+        if n == line {
+            write!(f, "{}> {:>width$} | ", indent, n, width = width)?;
+            term::paint(f, color, term::BOLD, text)?;
+            writeln!(f)?;
+            if let Some(column) = column.filter(|&c| c > 0) {
+                let pad = " ".repeat(column as usize - 1);
+                writeln!(f, "{}  {:>width$} | {}^", indent, "", pad, width = width)?;
+            }
+        } else {
+            let text = format!("  {:>width$} | {}", n, text, width = width);
+            write!(f, "{}", indent)?;
+            term::paint(f, color, term::DIM, &text.trim_end())?;
+            writeln!(f)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! An opt-in, in-process tally of which errors occur and how often.
+
+use crate::{fingerprint, frame, metadata::Utc, Details, Fingerprint, Frame};
+use std::{
+    backtrace::Backtrace,
+    collections::HashMap,
+    error::Error,
+    fmt,
+    panic::Location,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+    time::SystemTime,
+};
+
+/// Everything the stats registry knows about one [`Fingerprint`].
+#[derive(Clone, Debug)]
+pub struct ErrorStats {
+    pub fingerprint: Fingerprint,
+    pub type_name: &'static str,
+    /// The message of the first occurrence.
+    pub message: String,
+    /// Where the first occurrence was wrapped.
+    pub location: &'static Location<'static>,
+    pub count: u64,
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+    /// The backtrace of the first occurrence that had one, if any did.
+    pub frames: Vec<Frame>,
+}
+
+/// A copy of the stats registry, most frequent errors first. Its `Display`
+/// impl prints a summary for humans.
+#[derive(Clone, Debug, Default)]
+pub struct StatsSnapshot {
+    pub errors: Vec<ErrorStats>,
+    /// How many fingerprints were dropped, least recently seen first, to
+    /// stay within the registry's limit.
+    pub evicted: u64,
+}
+
+struct Registry {
+    limit: usize,
+    errors: HashMap<Fingerprint, ErrorStats>,
+    evicted: u64,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+
+/// Starts tallying every error wrapped from now on, keeping at most `limit`
+/// distinct fingerprints. Calling this again changes the limit and keeps
+/// what has been gathered so far.
+///
+/// Each wrapped error's backtrace is symbolized to keep with its tally,
+/// which costs far more than capturing it, so this is off by default.
+///
+/// ```
+/// use backtrace_error::{enable_stats, set_capture_policy, stats_snapshot, CapturePolicy, DynBacktraceError};
+///
+/// set_capture_policy(CapturePolicy::Always);
+/// enable_stats(100);
+/// for _ in 0..3 {
+///     let _ = DynBacktraceError::from(std::fmt::Error);
+/// }
+/// let stats = stats_snapshot();
+/// assert_eq!(stats.errors[0].count, 3);
+/// println!("{}", stats);
+/// ```
+pub fn enable_stats(limit: usize) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let registry = registry.get_or_insert_with(|| Registry {
+        limit,
+        errors: HashMap::new(),
+        evicted: 0,
+    });
+    registry.limit = limit.max(1);
+    while registry.errors.len() > registry.limit {
+        registry.evict();
+    }
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Stops tallying errors and discards everything gathered so far.
+pub fn disable_stats() {
+    ENABLED.store(false, Ordering::Relaxed);
+    *REGISTRY.lock().unwrap_or_else(PoisonError::into_inner) = None;
+}
+
+/// A copy of everything tallied since [`enable_stats`]; empty if stats are
+/// disabled.
+pub fn stats_snapshot() -> StatsSnapshot {
+    let registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(registry) = registry.as_ref() else {
+        return StatsSnapshot::default();
+    };
+    let mut errors: Vec<ErrorStats> = registry.errors.values().cloned().collect();
+    errors.sort_by(|a, b| b.count.cmp(&a.count).then(a.first_seen.cmp(&b.first_seen)));
+    StatsSnapshot {
+        errors,
+        evicted: registry.evicted,
+    }
+}
+
+impl Registry {
+    fn evict(&mut self) {
+        let oldest = self
+            .errors
+            .values()
+            .min_by_key(|stats| stats.last_seen)
+            .map(|stats| stats.fingerprint);
+        if let Some(oldest) = oldest {
+            self.errors.remove(&oldest);
+            self.evicted += 1;
+        }
+    }
+}
+
+/// Tallies a newly wrapped error, if stats are enabled.
+pub(crate) fn record(err: &dyn Error, backtrace: &Backtrace, details: &Details) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    // Symbolize before taking the lock.
+    let frames = Frame::from_backtrace(backtrace);
+    let fingerprint = fingerprint::compute(details);
+    let now = details
+        .metadata
+        .as_ref()
+        .map_or_else(SystemTime::now, |m| m.time);
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(registry) = registry.as_mut() else {
+        return;
+    };
+    if let Some(stats) = registry.errors.get_mut(&fingerprint) {
+        stats.count += 1;
+        stats.last_seen = now;
+        if stats.frames.is_empty() {
+            stats.frames = frames;
+        }
+        return;
+    }
+    if registry.errors.len() >= registry.limit {
+        registry.evict();
+    }
+    registry.errors.insert(
+        fingerprint,
+        ErrorStats {
+            fingerprint,
+            type_name: details.type_name,
+            message: err.to_string(),
+            location: details.location,
+            count: 1,
+            first_seen: now,
+            last_seen: now,
+            frames,
+        },
+    );
+}
+
+impl fmt::Display for StatsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} distinct errors", self.errors.len())?;
+        if self.evicted > 0 {
+            write!(f, " ({} more evicted)", self.evicted)?;
+        }
+        writeln!(f)?;
+        for stats in &self.errors {
+            writeln!(
+                f,
+                "{:>8}x {} {}: {}",
+                stats.count, stats.fingerprint, stats.type_name, stats.message
+            )?;
+            writeln!(
+                f,
+                "          first seen {}, last seen {}",
+                Utc(stats.first_seen),
+                Utc(stats.last_seen)
+            )?;
+            let range = frame::interesting_range(&stats.frames);
+            if range.is_empty() {
+                writeln!(f, "          at {}", stats.location)?;
+            }
+            for frame in &stats.frames[range] {
+                writeln!(f, "          {}", frame)?;
+            }
+        }
+        Ok(())
+    }
+}
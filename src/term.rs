@@ -0,0 +1,89 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+//! Terminal detection and ANSI styling for reports.
+
+use std::{
+    env,
+    fmt::{self, Display, Write},
+    io::{self, IsTerminal},
+    path::Path,
+};
+
+pub(crate) const RED: &str = "1;31";
+pub(crate) const BOLD: &str = "1";
+pub(crate) const DIM: &str = "2";
+pub(crate) const UNDERLINE: &str = "4";
+
+/// Whether reports written to stderr should be colored: never if `NO_COLOR`
+/// is set to anything, always if `CLICOLOR_FORCE` is set to anything but
+/// `0`, and otherwise only if stderr is a terminal. See
+/// <https://no-color.org> and <https://bixense.com/clicolors>.
+pub(crate) fn stderr_color() -> bool {
+    if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return false;
+    }
+    if env::var_os("CLICOLOR_FORCE").is_some_and(|v| !v.is_empty() && v != "0") {
+        return true;
+    }
+    io::stderr().is_terminal()
+}
+
+/// Whether reports written to stderr should link locations to their source
+/// files: only if asked for with `BACKTRACE_ERROR_HYPERLINKS` (set to
+/// anything but `0`) or by setting an editor URL template in
+/// `BACKTRACE_ERROR_EDITOR`, and stderr is a terminal.
+pub(crate) fn stderr_hyperlinks() -> bool {
+    let wanted = env::var_os("BACKTRACE_ERROR_HYPERLINKS").is_some_and(|v| v != "0")
+        || env::var_os("BACKTRACE_ERROR_EDITOR").is_some();
+    wanted && io::stderr().is_terminal()
+}
+
+/// The URL a location links to. `BACKTRACE_ERROR_EDITOR` may hold a template
+/// such as `vscode://file/{path}:{line}:{column}`; otherwise it is a
+/// `file://` URL with the line number as the fragment, which some terminals
+/// pass on to the editor they open.
+pub(crate) fn link_url(path: &Path, line: Option<u32>, column: Option<u32>) -> String {
+    let path = match env::current_dir() {
+        Ok(cwd) if path.is_relative() => cwd.join(path),
+        _ => path.to_path_buf(),
+    };
+    let line = line.map_or(String::new(), |n| n.to_string());
+    let column = column.map_or(String::new(), |n| n.to_string());
+    match env::var("BACKTRACE_ERROR_EDITOR") {
+        Ok(template) if !template.is_empty() => template
+            .replace("{path}", &path.to_string_lossy())
+            .replace("{line}", &line)
+            .replace("{column}", &column),
+        _ => {
+            let mut url = String::from("file://");
+            for &b in path.to_string_lossy().as_bytes() {
+                match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                        url.push(b as char)
+                    }
+                    _ => url.push_str(&format!("%{:02X}", b)),
+                }
+            }
+            if !line.is_empty() {
+                url.push('#');
+                url.push_str(&line);
+            }
+            url
+        }
+    }
+}
+
+/// Writes `text` as an OSC 8 hyperlink to `url`.
+pub(crate) fn hyperlink(f: &mut dyn Write, url: &str, text: &dyn Display) -> fmt::Result {
+    write!(f, "\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Writes `text`, wrapped in the ANSI escape for `style` if `on`.
+pub(crate) fn paint(f: &mut dyn Write, on: bool, style: &str, text: &dyn Display) -> fmt::Result {
+    if on {
+        write!(f, "\x1b[{}m{}\x1b[0m", style, text)
+    } else {
+        write!(f, "{}", text)
+    }
+}
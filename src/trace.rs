@@ -0,0 +1,55 @@
+// Copyright 2021-2024 Graydon Hoare <graydon@pobox.com>
+// Licensed under ASL2 or MIT
+
+use crate::{BacktraceError, DynBacktraceError};
+use std::{error::Error, panic::Location};
+
+/// Records where an error is returned through, building up an error return
+/// trace.
+///
+/// The backtrace only shows where an error was first wrapped. Calling
+/// `.trace()` on a result as it is returned appends the location of that
+/// call to a list kept in the wrapper, printed after the backtrace under
+/// "Propagated through:". This follows the error across threads, channels
+/// and callbacks, which the backtrace cannot, and costs one `Vec` push per
+/// hop, only on the error path.
+///
+/// ```
+/// use backtrace_error::{DynBacktraceError, Trace};
+/// use std::thread;
+///
+/// fn parse(s: &str) -> Result<i32, DynBacktraceError> {
+///     Ok(s.parse::<i32>()?)
+/// }
+///
+/// fn worker() -> Result<i32, DynBacktraceError> {
+///     parse("nope").trace()
+/// }
+///
+/// let err = thread::spawn(worker).join().unwrap().trace().unwrap_err();
+/// assert_eq!(err.return_trace().len(), 2);
+/// assert!(err.to_string().contains("Propagated through:"));
+/// ```
+pub trait Trace: Sized {
+    fn trace(self) -> Self;
+}
+
+impl<T, E: Error + 'static> Trace for Result<T, BacktraceError<E>> {
+    #[track_caller]
+    fn trace(mut self) -> Self {
+        if let Err(err) = &mut self {
+            err.details.trace.push(Location::caller());
+        }
+        self
+    }
+}
+
+impl<T> Trace for Result<T, DynBacktraceError> {
+    #[track_caller]
+    fn trace(mut self) -> Self {
+        if let Err(err) = &mut self {
+            err.details.trace.push(Location::caller());
+        }
+        self
+    }
+}
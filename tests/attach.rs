@@ -0,0 +1,74 @@
+use backtrace_error::{BacktraceError, Context, DynBacktraceError};
+use std::{fmt, num::ParseIntError, path::PathBuf};
+
+#[derive(Debug, PartialEq)]
+struct RequestId(u64);
+
+#[derive(Debug, PartialEq)]
+struct Status(u16);
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP status {}", self.0)
+    }
+}
+
+fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    Ok(s.parse::<i32>()?)
+}
+
+#[test]
+fn values_are_retrieved_by_type() {
+    let err = parse("x")
+        .unwrap_err()
+        .attach(RequestId(7))
+        .attach(PathBuf::from("/etc/app.toml"))
+        .attach_printable(Status(503));
+    assert_eq!(err.request_ref::<RequestId>(), Some(&RequestId(7)));
+    assert_eq!(
+        err.request_ref::<PathBuf>().unwrap().to_str(),
+        Some("/etc/app.toml")
+    );
+    assert_eq!(err.request_ref::<Status>(), Some(&Status(503)));
+    assert_eq!(err.request_ref::<u32>(), None);
+}
+
+#[test]
+fn latest_value_of_a_type_wins() {
+    let err = DynBacktraceError::from(fmt::Error)
+        .attach(3u32)
+        .attach(4u32);
+    assert_eq!(err.request_ref::<u32>(), Some(&4));
+}
+
+#[test]
+fn only_printable_values_are_reported() {
+    let err = DynBacktraceError::from(fmt::Error)
+        .attach(RequestId(7))
+        .attach_printable(Status(503));
+    let report = err.to_string();
+    assert!(
+        report.contains("Attachments:\n    HTTP status 503\n"),
+        "{}",
+        report
+    );
+    assert!(!report.contains("RequestId"), "{}", report);
+
+    let err = DynBacktraceError::from(fmt::Error).attach(RequestId(7));
+    assert!(!err.to_string().contains("Attachments:"));
+}
+
+#[test]
+fn attachments_follow_the_error_through_wrapping() {
+    let err = parse("x").unwrap_err().attach(RequestId(1));
+    let err = Err::<(), _>(err).context("parsing").unwrap_err();
+    let err = err.attach_printable(Status(400));
+    let flat: BacktraceError<ParseIntError> = err.into();
+    assert_eq!(flat.request_ref::<RequestId>(), Some(&RequestId(1)));
+    assert_eq!(flat.request_ref::<Status>(), Some(&Status(400)));
+
+    let dyn_err = DynBacktraceError::from(flat).attach(RequestId(2));
+    assert_eq!(dyn_err.request_ref::<RequestId>(), Some(&RequestId(2)));
+    assert_eq!(dyn_err.request_ref::<Status>(), Some(&Status(400)));
+    assert!(dyn_err.to_string().contains("HTTP status 400"));
+}
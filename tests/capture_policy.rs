@@ -0,0 +1,69 @@
+use backtrace_error::{
+    capture_policy, register_has_backtrace, set_capture_policy, BacktraceError, CapturePolicy,
+    DynBacktraceError,
+};
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    fmt, ptr,
+};
+
+mod common;
+
+use common::Traced;
+
+#[test]
+fn policy_controls_capture() {
+    if std::env::var_os("BACKTRACE_ERROR_CAPTURE").is_none() {
+        assert_eq!(capture_policy(), CapturePolicy::Env);
+    }
+
+    set_capture_policy(CapturePolicy::Always);
+    assert_eq!(capture_policy(), CapturePolicy::Always);
+    let err: BacktraceError<fmt::Error> = fmt::Error.into();
+    assert_eq!(err.backtrace.status(), BacktraceStatus::Captured);
+    assert!(!err.frames().is_empty());
+    let err = DynBacktraceError::from(fmt::Error);
+    assert_eq!(err.backtrace().status(), BacktraceStatus::Captured);
+
+    set_capture_policy(CapturePolicy::Never);
+    let err: BacktraceError<fmt::Error> = fmt::Error.into();
+    assert_eq!(err.backtrace.status(), BacktraceStatus::Disabled);
+    let err = DynBacktraceError::from(fmt::Error);
+    assert_eq!(err.backtrace().status(), BacktraceStatus::Disabled);
+    assert!(err.to_string().contains("disabled backtrace"));
+
+    set_capture_policy(CapturePolicy::DebugOnly);
+    let err = DynBacktraceError::from(fmt::Error);
+    let expected = if cfg!(debug_assertions) {
+        BacktraceStatus::Captured
+    } else {
+        BacktraceStatus::Disabled
+    };
+    assert_eq!(err.backtrace().status(), expected);
+
+    // A carried backtrace is only reused if it was captured.
+    set_capture_policy(CapturePolicy::Always);
+    register_has_backtrace::<Traced>();
+    let err = DynBacktraceError::from(Traced(Backtrace::disabled()));
+    assert_eq!(err.backtrace().status(), BacktraceStatus::Captured);
+    assert!(err.to_string().contains("Error context:\n"));
+
+    // Our own wrappers settle it once, when the error is first wrapped.
+    set_capture_policy(CapturePolicy::Never);
+    let typed: BacktraceError<fmt::Error> = fmt::Error.into();
+    set_capture_policy(CapturePolicy::Always);
+    let err = DynBacktraceError::from(typed);
+    assert_eq!(err.backtrace().status(), BacktraceStatus::Disabled);
+    let report = err.to_string();
+    assert!(report.contains("disabled backtrace"), "{}", report);
+    assert_eq!(report.matches("Initial error:").count(), 1);
+
+    // ...and a captured one still is.
+    let traced = Traced(Backtrace::force_capture());
+    let err = DynBacktraceError::from(traced);
+    let carried = &err.downcast_ref::<Traced>().unwrap().0;
+    assert!(ptr::eq(err.backtrace(), carried));
+
+    set_capture_policy(CapturePolicy::Env);
+    assert_eq!(capture_policy(), CapturePolicy::Env);
+}
@@ -0,0 +1,55 @@
+use backtrace_error::{set_capture_policy, CapturePolicy, DynBacktraceError};
+use std::{env, fmt};
+
+fn fail() -> DynBacktraceError {
+    DynBacktraceError::from(fmt::Error)
+}
+
+#[test]
+fn plain_by_default() {
+    let err = fail();
+    assert!(!err.to_string().contains('\x1b'));
+    assert!(!err.report().color(false).to_string().contains('\x1b'));
+}
+
+#[test]
+fn colored_on_request_and_from_env() {
+    set_capture_policy(CapturePolicy::Always);
+    let err = fail();
+    let colored = err.report().color(true).to_string();
+    assert!(colored.contains("\x1b[1;31mInitial error: an error occurred"));
+    assert!(colored.contains("\x1b[1mcolor::fail\x1b[0m"), "{}", colored);
+    assert!(
+        colored.contains("at \x1b[4m./tests/color.rs:5:"),
+        "{}",
+        colored
+    );
+    // Stripping the escapes gives back the plain report.
+    let mut stripped = colored.clone();
+    while let Some(start) = stripped.find('\x1b') {
+        let end = start + stripped[start..].find('m').unwrap();
+        stripped.replace_range(start..=end, "");
+    }
+    assert_eq!(stripped, err.to_string());
+
+    // The test harness captures stderr, so it isn't a terminal.
+    env::remove_var("NO_COLOR");
+    env::remove_var("CLICOLOR_FORCE");
+    let on_stderr = |err: &DynBacktraceError| err.report().stderr().to_string().contains('\x1b');
+    if !std::io::IsTerminal::is_terminal(&std::io::stderr()) {
+        assert!(!on_stderr(&err));
+    }
+    env::set_var("CLICOLOR_FORCE", "1");
+    assert!(on_stderr(&err));
+    env::set_var("CLICOLOR_FORCE", "0");
+    env::set_var("NO_COLOR", "");
+    if !std::io::IsTerminal::is_terminal(&std::io::stderr()) {
+        assert!(!on_stderr(&err));
+    }
+    env::set_var("CLICOLOR_FORCE", "1");
+    env::set_var("NO_COLOR", "1");
+    assert!(!on_stderr(&err));
+    env::remove_var("NO_COLOR");
+    env::remove_var("CLICOLOR_FORCE");
+    set_capture_policy(CapturePolicy::Env);
+}
@@ -0,0 +1,35 @@
+// Shared by several test binaries, each of which uses only some of it.
+#![allow(dead_code)]
+
+// The capture policy, sampling, environment variables and registries are
+// process-wide, so each test binary changes them from only one test.
+
+use backtrace_error::HasBacktrace;
+use std::{backtrace::Backtrace, error::Error, fmt};
+
+/// The report without its header line (which varies from run to run), i.e.
+/// starting from "Initial error:".
+pub fn body(report: &str) -> &str {
+    report
+        .find("Initial error:")
+        .map_or(report, |start| &report[start..])
+}
+
+/// An error that carries its own backtrace. Register it with
+/// `register_has_backtrace::<Traced>()` before use.
+#[derive(Debug)]
+pub struct Traced(pub Backtrace);
+
+impl fmt::Display for Traced {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("traced")
+    }
+}
+
+impl Error for Traced {}
+
+impl HasBacktrace for Traced {
+    fn backtrace(&self) -> Option<&Backtrace> {
+        Some(&self.0)
+    }
+}
@@ -0,0 +1,89 @@
+use backtrace_error::{BacktraceError, Context, DynBacktraceError, HasBacktrace};
+use std::{num::ParseIntError, ptr};
+
+mod common;
+
+fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    s.parse::<i32>().context("parsing port")
+}
+
+#[test]
+fn context_on_plain_result_wraps() {
+    let err = parse("http").unwrap_err();
+    assert_eq!(err.context(), ["parsing port"]);
+    let report = err.to_string();
+    assert!(common::body(&report).starts_with(
+        "Initial error: invalid digit found in string\nContext:\n    parsing port\nError context:\n"
+    ));
+}
+
+#[test]
+fn context_on_typed_result_does_not_recapture() {
+    let nested = parse("http").context("reading config").unwrap_err();
+    let carried = HasBacktrace::backtrace(&nested).unwrap();
+    assert!(ptr::eq(carried, &*nested.inner.backtrace));
+    let report = nested.to_string();
+    assert!(report.contains("Context:\n    parsing port\n    reading config\n"));
+    assert_eq!(report.matches("Initial error:").count(), 1);
+
+    let flat: BacktraceError<ParseIntError> = nested.into();
+    assert_eq!(flat.context(), ["parsing port", "reading config"]);
+}
+
+#[test]
+fn question_mark_flattens_nested_context() {
+    fn load() -> Result<i32, BacktraceError<ParseIntError>> {
+        Ok(parse("http").with_context(|| format!("loading {}", "app.toml"))?)
+    }
+    let err = load().unwrap_err();
+    assert_eq!(err.context(), ["parsing port", "loading app.toml"]);
+}
+
+#[test]
+fn push_context_adds_to_typed_wrapper() {
+    let err = parse("http").unwrap_err();
+    let id = err.id();
+    let err = err
+        .push_context("reading config")
+        .push_context("starting up");
+    assert_eq!(err.id(), id);
+    assert_eq!(
+        err.context(),
+        ["parsing port", "reading config", "starting up"]
+    );
+    let report = err.to_string();
+    assert!(report.contains("    parsing port\n    reading config\n    starting up\n"));
+}
+
+#[test]
+fn context_on_dyn_result_pushes() {
+    fn run() -> Result<i32, DynBacktraceError> {
+        let port: i32 = "http".parse()?;
+        Ok(port)
+    }
+    let err = run()
+        .context("parsing port")
+        .context("starting server")
+        .unwrap_err();
+    assert_eq!(err.context(), ["parsing port", "starting server"]);
+    assert!(err
+        .to_string()
+        .contains("    parsing port\n    starting server\n"));
+}
+
+#[test]
+fn context_survives_into_dyn_and_downcast() {
+    let err = parse("http").unwrap_err().into_dyn();
+    let err = Err::<(), _>(err).context("outer").unwrap_err();
+    let typed = err
+        .downcast::<ParseIntError>()
+        .unwrap_or_else(|_| panic!("downcast failed"));
+    assert_eq!(typed.context(), ["parsing port", "outer"]);
+}
+
+#[test]
+fn with_context_is_lazy() {
+    let ok: Result<i32, ParseIntError> = Ok(1);
+    let res = ok.with_context(|| -> String { panic!("should not be called") });
+    assert_eq!(res.unwrap(), 1);
+}
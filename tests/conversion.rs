@@ -0,0 +1,55 @@
+use backtrace_error::{set_capture_policy, BacktraceError, CapturePolicy, DynBacktraceError};
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    io,
+    num::ParseIntError,
+    ptr,
+};
+
+/// Wraps a parse error with a backtrace captured regardless of the
+/// environment, so the tests can tell it apart from a recaptured one.
+fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    set_capture_policy(CapturePolicy::Always);
+    Ok(s.parse::<i32>()?)
+}
+
+fn captured(typed: &BacktraceError<ParseIntError>) -> *const Backtrace {
+    assert_eq!(typed.backtrace.status(), BacktraceStatus::Captured);
+    &*typed.backtrace
+}
+
+#[test]
+fn into_dyn_moves_backtrace() {
+    let typed = parse("nope").unwrap_err();
+    let before = captured(&typed);
+    let erased = typed.into_dyn();
+    assert!(erased.is::<ParseIntError>());
+    assert!(ptr::eq(erased.backtrace(), before));
+}
+
+#[test]
+fn into_dyn_round_trips_through_downcast() {
+    let typed = parse("nope").unwrap_err();
+    let before = captured(&typed);
+    let typed = typed
+        .into_dyn()
+        .downcast::<ParseIntError>()
+        .unwrap_or_else(|_| panic!("downcast failed"));
+    assert!(ptr::eq(&*typed.backtrace, before));
+}
+
+#[test]
+fn downcast_unwraps_nested_typed_error() {
+    let typed = parse("nope").unwrap_err();
+    let before = captured(&typed);
+    // Converting with `From` boxes the whole typed wrapper.
+    let erased = DynBacktraceError::from(typed);
+    assert!(!erased.is::<ParseIntError>());
+    let typed = erased
+        .downcast::<ParseIntError>()
+        .unwrap_or_else(|_| panic!("downcast failed"));
+    assert!(ptr::eq(&*typed.backtrace, before));
+    assert!(DynBacktraceError::from(typed)
+        .downcast::<io::Error>()
+        .is_err());
+}
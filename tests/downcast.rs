@@ -0,0 +1,51 @@
+use backtrace_error::{set_capture_policy, CapturePolicy, DynBacktraceError};
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    fs, io,
+    num::ParseIntError,
+    ptr,
+};
+
+fn open_file() -> Result<fs::File, DynBacktraceError> {
+    Ok(fs::File::open("/does-not-exist.nope")?)
+}
+
+#[test]
+fn is_and_downcast_ref() {
+    let err = open_file().unwrap_err();
+    assert!(err.is::<io::Error>());
+    assert!(!err.is::<ParseIntError>());
+    let io = err.downcast_ref::<io::Error>().unwrap();
+    assert_eq!(io.kind(), io::ErrorKind::NotFound);
+    assert!(err.downcast_ref::<ParseIntError>().is_none());
+}
+
+#[test]
+fn downcast_mut_modifies_inner() {
+    let mut err: DynBacktraceError = io::Error::new(io::ErrorKind::Other, "before").into();
+    *err.downcast_mut::<io::Error>().unwrap() = io::Error::new(io::ErrorKind::Other, "after");
+    assert_eq!(
+        err.downcast_ref::<io::Error>().unwrap().to_string(),
+        "after"
+    );
+}
+
+#[test]
+fn downcast_preserves_backtrace() {
+    set_capture_policy(CapturePolicy::Always);
+    let err = open_file().unwrap_err();
+    assert_eq!(err.backtrace().status(), BacktraceStatus::Captured);
+    let before: *const Backtrace = err.backtrace();
+    let err = err.downcast::<ParseIntError>().unwrap_err();
+    assert!(ptr::eq(err.backtrace(), before));
+    let typed = err.downcast::<io::Error>().unwrap();
+    assert_eq!(typed.inner.kind(), io::ErrorKind::NotFound);
+    assert!(ptr::eq(&*typed.backtrace, before));
+}
+
+#[test]
+fn into_inner_returns_boxed_error() {
+    let err = open_file().unwrap_err();
+    let inner = err.into_inner();
+    assert!(inner.downcast_ref::<io::Error>().is_some());
+}
@@ -0,0 +1,58 @@
+use backtrace_error::{register_has_backtrace, DynBacktraceError};
+use std::backtrace::Backtrace;
+
+mod common;
+
+use common::Traced;
+
+#[inline(never)]
+fn fail() -> Result<(), DynBacktraceError> {
+    Err(Traced(Backtrace::force_capture()))?
+}
+
+fn backtrace_lines(report: &str) -> Vec<&str> {
+    report
+        .lines()
+        .skip_while(|l| *l != "Error context:")
+        .skip(1)
+        .collect()
+}
+
+fn full_from_env() -> bool {
+    std::env::var("RUST_LIB_BACKTRACE")
+        .or_else(|_| std::env::var("RUST_BACKTRACE"))
+        .is_ok_and(|v| v == "full")
+}
+
+#[test]
+fn default_display_trims_capture_and_runtime_frames() {
+    if full_from_env() {
+        return;
+    }
+    register_has_backtrace::<Traced>();
+    let err = fail().unwrap_err();
+    let report = err.to_string();
+    let lines = backtrace_lines(&report);
+    assert!(lines[0].ends_with(": filter::fail"), "{}", report);
+    assert!(!report.contains("std::backtrace"));
+    assert!(!report.contains("__rust_begin_short_backtrace"));
+    let last = lines.last().unwrap();
+    assert!(last.contains("frames hidden"), "{}", report);
+}
+
+#[test]
+fn alternate_display_shows_every_frame() {
+    register_has_backtrace::<Traced>();
+    let err = fail().unwrap_err();
+    let report = format!("{:#}", err);
+    assert!(report.contains("Backtrace>::force_capture"));
+    assert!(report.contains("__rust_begin_short_backtrace"));
+    assert!(!report.contains("frames hidden"));
+    assert_eq!(
+        backtrace_lines(&report)
+            .iter()
+            .filter(|l| !l.trim_start().starts_with("at "))
+            .count(),
+        err.frames().len()
+    );
+}
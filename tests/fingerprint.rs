@@ -0,0 +1,103 @@
+use backtrace_error::{
+    set_capture_policy, set_sampling, BacktraceError, CapturePolicy, DynBacktraceError,
+    Fingerprint, Sampling,
+};
+use std::{fmt, io};
+
+#[inline(never)]
+fn fail_here() -> DynBacktraceError {
+    DynBacktraceError::from(fmt::Error)
+}
+
+#[inline(never)]
+fn fail_there() -> DynBacktraceError {
+    DynBacktraceError::from(fmt::Error)
+}
+
+#[inline(never)]
+fn fail_io() -> DynBacktraceError {
+    DynBacktraceError::from(io::Error::new(io::ErrorKind::Other, "x"))
+}
+
+#[inline(never)]
+fn helper() -> Result<(), DynBacktraceError> {
+    Err(fmt::Error)?
+}
+
+#[inline(never)]
+fn caller_a() -> DynBacktraceError {
+    helper().unwrap_err()
+}
+
+#[inline(never)]
+fn caller_b() -> DynBacktraceError {
+    helper().unwrap_err()
+}
+
+#[inline(never)]
+fn fail_with<E: std::error::Error + Send + Sync + 'static>(err: E) -> DynBacktraceError {
+    DynBacktraceError::from(err)
+}
+
+#[test]
+fn groups_by_type_and_site() {
+    set_capture_policy(CapturePolicy::Always);
+    let err = fail_here();
+    assert!(err
+        .frames()
+        .iter()
+        .any(|f| f.symbol.as_deref().is_some_and(|s| s.contains("fail_here"))));
+
+    let prints: Vec<Fingerprint> = (0..5).map(|_| fail_here().fingerprint()).collect();
+    assert!(prints.iter().all(|p| *p == prints[0]));
+    // Distinct instances, same group.
+    assert_ne!(fail_here().id(), fail_here().id());
+
+    let here = fail_here().fingerprint();
+    assert_ne!(here, fail_there().fingerprint());
+    assert_ne!(here, fail_io().fingerprint());
+    // Same site, different type.
+    assert_ne!(
+        fail_with(fmt::Error).fingerprint(),
+        fail_with(io::Error::new(io::ErrorKind::Other, "x")).fingerprint()
+    );
+
+    // Only the wrap site counts, not how it was reached: both callers of a
+    // shared helper get the helper's fingerprint.
+    let (a, b) = (caller_a(), caller_b());
+    let called_from = |err: &DynBacktraceError, caller: &str| {
+        err.frames()
+            .iter()
+            .any(|f| f.symbol.as_deref().is_some_and(|s| s.ends_with(caller)))
+    };
+    assert!(called_from(&a, "caller_a") && called_from(&b, "caller_b"));
+    assert_eq!(a.fingerprint(), b.fingerprint());
+
+    // Whether sampling captured a backtrace or not makes no difference.
+    set_sampling(Sampling::OneIn(2));
+    let errs: Vec<DynBacktraceError> = (0..4).map(|_| fail_here()).collect();
+    set_sampling(Sampling::All);
+    set_capture_policy(CapturePolicy::Env);
+    assert!(errs.iter().any(|err| err.frames().is_empty()));
+    assert!(errs.iter().any(|err| !err.frames().is_empty()));
+    assert!(errs.iter().all(|err| err.fingerprint() == here));
+}
+
+#[test]
+fn rewrapping_keeps_fingerprint() {
+    let typed: BacktraceError<fmt::Error> = BacktraceError::new(fmt::Error);
+    let print = typed.fingerprint();
+    let erased = DynBacktraceError::from(typed);
+    assert_eq!(erased.fingerprint(), print);
+    assert_eq!(
+        erased.downcast::<fmt::Error>().unwrap().fingerprint(),
+        print
+    );
+}
+
+#[test]
+fn displays_as_hex() {
+    let text = fail_here().fingerprint().to_string();
+    assert_eq!(text.len(), 16);
+    assert!(text.bytes().all(|b| b.is_ascii_hexdigit()));
+}
@@ -0,0 +1,78 @@
+use backtrace_error::{register_has_backtrace, BacktraceError, DynBacktraceError, Frame};
+use std::{backtrace::Backtrace, fmt};
+
+mod common;
+
+use common::Traced;
+
+#[inline(never)]
+fn capture_here() -> (Vec<Frame>, u32) {
+    (Frame::from_backtrace(&Backtrace::force_capture()), line!())
+}
+
+#[test]
+fn frames_of_real_capture() {
+    let (frames, line) = capture_here();
+    let frame = frames
+        .iter()
+        .find(|f| f.symbol.as_deref() == Some("frames::capture_here"))
+        .expect("capture_here frame");
+    assert!(frame.file.as_deref().unwrap().ends_with("frames.rs"));
+    assert_eq!(frame.line, Some(line));
+    assert!(frame.column.is_some());
+    assert!(!frame.is_inlined);
+
+    // Symbols are normalized: no trailing hashes or crate disambiguators.
+    for symbol in frames.iter().filter_map(|f| f.symbol.as_deref()) {
+        let hashed = symbol
+            .rsplit_once("::h")
+            .is_some_and(|(_, h)| h.len() == 16);
+        assert!(!hashed, "{}", symbol);
+        assert!(!symbol.contains("std["), "{}", symbol);
+    }
+}
+
+#[test]
+fn frames_display() {
+    let frame = Frame {
+        symbol: Some("app::main".to_string()),
+        file: Some("src/main.rs".to_string()),
+        line: Some(3),
+        column: Some(9),
+        ip: None,
+        is_inlined: false,
+    };
+    assert_eq!(frame.to_string(), "app::main at src/main.rs:3:9");
+}
+
+#[test]
+fn uncaptured_backtraces_have_no_frames() {
+    // Tests run without RUST_BACKTRACE, so wrappers capture nothing.
+    if std::env::var_os("RUST_BACKTRACE").is_none()
+        && std::env::var_os("RUST_LIB_BACKTRACE").is_none()
+    {
+        let err: BacktraceError<fmt::Error> = fmt::Error.into();
+        assert!(err.frames().is_empty());
+        let err = DynBacktraceError::from(fmt::Error);
+        assert!(err.frames().is_empty());
+    }
+}
+
+#[test]
+fn wrapper_frames_come_from_carried_backtrace() {
+    register_has_backtrace::<Traced>();
+    let err = DynBacktraceError::from(Traced(Backtrace::force_capture()));
+    let frames = err.frames();
+    assert!(
+        frames
+            .iter()
+            .any(|f| f.symbol.as_deref()
+                == Some("frames::wrapper_frames_come_from_carried_backtrace"))
+    );
+
+    let err: BacktraceError<Traced> = Traced(Backtrace::force_capture()).into();
+    assert_eq!(
+        err.frames().len(),
+        Frame::from_backtrace(&err.inner.0).len()
+    );
+}
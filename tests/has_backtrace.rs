@@ -0,0 +1,83 @@
+use backtrace_error::{register_has_backtrace, BacktraceError, DynBacktraceError, HasBacktrace};
+use std::{backtrace::Backtrace, fmt, num::ParseIntError, ptr};
+
+mod common;
+
+fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    Ok(s.parse::<i32>()?)
+}
+
+#[test]
+fn dyn_from_typed_reuses_backtrace() {
+    fn run() -> Result<i32, DynBacktraceError> {
+        Ok(parse("nope")?)
+    }
+    let err = run().unwrap_err();
+    let typed = err.downcast_ref::<BacktraceError<ParseIntError>>().unwrap();
+    assert!(ptr::eq(err.backtrace(), &*typed.backtrace));
+    let report = err.to_string();
+    assert!(common::body(&report).starts_with("Initial error: invalid digit found in string\n"));
+    assert_eq!(report.matches("Initial error:").count(), 1);
+}
+
+#[test]
+fn typed_from_typed_reuses_backtrace() {
+    let inner = parse("nope").unwrap_err();
+    let outer: BacktraceError<BacktraceError<ParseIntError>> = BacktraceError::from(inner);
+    let carried = HasBacktrace::backtrace(&outer).unwrap();
+    assert!(ptr::eq(carried, &*outer.inner.backtrace));
+    assert!(common::body(&outer.to_string())
+        .starts_with("Initial error: invalid digit found in string\n"));
+}
+
+#[derive(Debug)]
+enum LoadError {
+    Missing { backtrace: Backtrace },
+    Empty,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Missing { .. } => f.write_str("missing"),
+            LoadError::Empty => f.write_str("empty"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl HasBacktrace for LoadError {
+    fn backtrace(&self) -> Option<&Backtrace> {
+        match self {
+            LoadError::Missing { backtrace } => Some(backtrace),
+            LoadError::Empty => None,
+        }
+    }
+}
+
+#[test]
+fn registered_user_type_is_consulted() {
+    register_has_backtrace::<LoadError>();
+
+    let err = DynBacktraceError::from(LoadError::Missing {
+        backtrace: Backtrace::force_capture(),
+    });
+    let LoadError::Missing { backtrace } = err.downcast_ref::<LoadError>().unwrap() else {
+        panic!("wrong variant");
+    };
+    assert!(ptr::eq(err.backtrace(), backtrace));
+
+    let err = BacktraceError::from(LoadError::Missing {
+        backtrace: Backtrace::force_capture(),
+    });
+    let LoadError::Missing { backtrace } = &err.inner else {
+        panic!("wrong variant");
+    };
+    assert!(ptr::eq(HasBacktrace::backtrace(&err).unwrap(), backtrace));
+
+    // Variants without a backtrace still get one captured.
+    let err = DynBacktraceError::from(LoadError::Empty);
+    assert!(err.downcast_ref::<LoadError>().is_some());
+    assert!(HasBacktrace::backtrace(&err).is_some());
+}
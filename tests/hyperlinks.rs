@@ -0,0 +1,48 @@
+use backtrace_error::{set_capture_policy, CapturePolicy, DynBacktraceError};
+use std::{env, fmt};
+
+fn fail() -> DynBacktraceError {
+    DynBacktraceError::from(fmt::Error)
+}
+
+fn link_to(report: &str) -> Option<&str> {
+    let start = report.find("\x1b]8;;")? + 5;
+    let end = start + report[start..].find('\x1b')?;
+    Some(&report[start..end])
+}
+
+#[test]
+fn locations_link_to_source() {
+    set_capture_policy(CapturePolicy::Always);
+    env::remove_var("BACKTRACE_ERROR_EDITOR");
+    let err = fail();
+    assert!(!err.to_string().contains("\x1b]8"));
+
+    let report = err.report().hyperlinks(true).to_string();
+    let file = env::current_dir().unwrap().join(file!());
+    let expected = format!("file://{}#5", file.display());
+    assert_eq!(link_to(&report), Some(expected.as_str()), "{:?}", report);
+    // The visible text is unchanged, and the link is closed after it.
+    assert!(report.contains(&format!(
+        "{}\x1b\\./tests/hyperlinks.rs:5:5\x1b]8;;\x1b\\\n",
+        expected
+    )));
+
+    env::set_var(
+        "BACKTRACE_ERROR_EDITOR",
+        "vscode://file/{path}:{line}:{column}",
+    );
+    let report = err.report().hyperlinks(true).to_string();
+    let expected = format!("vscode://file/{}:5:5", file.display());
+    assert_eq!(link_to(&report), Some(expected.as_str()));
+
+    // Even when asked for, no links unless stderr is a terminal, which it
+    // isn't under the test harness.
+    if !std::io::IsTerminal::is_terminal(&std::io::stderr()) {
+        env::set_var("BACKTRACE_ERROR_HYPERLINKS", "1");
+        assert!(!err.report().stderr().to_string().contains("\x1b]8"));
+        env::remove_var("BACKTRACE_ERROR_HYPERLINKS");
+    }
+    env::remove_var("BACKTRACE_ERROR_EDITOR");
+    set_capture_policy(CapturePolicy::Env);
+}
@@ -0,0 +1,51 @@
+use backtrace_error::{BacktraceError, DynBacktraceError};
+use std::{collections::HashSet, fmt, thread};
+
+#[test]
+fn ids_are_unique() {
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            thread::spawn(|| {
+                (0..100)
+                    .map(|_| DynBacktraceError::from(fmt::Error).id())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let ids: HashSet<_> = handles
+        .into_iter()
+        .flat_map(|h| h.join().unwrap())
+        .collect();
+    assert_eq!(ids.len(), 400);
+    assert_eq!(
+        ids.iter()
+            .map(|id| id.process)
+            .collect::<HashSet<_>>()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn id_is_compact_and_in_report() {
+    let err = BacktraceError::new(fmt::Error);
+    let id = err.id().to_string();
+    let (prefix, seq) = id.split_once('-').unwrap();
+    assert_eq!(prefix.len(), 8);
+    assert!(prefix.bytes().all(|b| b.is_ascii_hexdigit()));
+    assert!(seq.parse::<u64>().is_ok());
+    let report = err.to_string();
+    assert!(report.starts_with(&format!("Error {}", id)), "{}", report);
+    assert_eq!(report.matches(&id).count(), 1);
+}
+
+#[test]
+fn rewrapping_keeps_the_id() {
+    let inner = BacktraceError::new(fmt::Error);
+    let id = inner.id();
+    let outer = DynBacktraceError::from(inner);
+    assert_eq!(outer.id(), id);
+    assert!(outer.to_string().starts_with(&format!("Error {}", id)));
+    let typed = outer.downcast::<fmt::Error>().unwrap();
+    assert_eq!(typed.id(), id);
+}
@@ -0,0 +1,52 @@
+use backtrace_error::{
+    set_capture_policy, BacktraceError, BtExt, CapturePolicy, Context, DynBacktraceError,
+};
+use std::{fmt, num::ParseIntError};
+
+fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    Ok(s.parse::<i32>()?)
+}
+
+#[test]
+fn question_mark_records_its_line() {
+    let err = parse("x").unwrap_err();
+    assert_eq!(err.location().file(), file!());
+    assert_eq!(err.location().line(), 7);
+}
+
+#[test]
+fn constructors_and_bt_record_the_caller() {
+    let line = line!() + 1;
+    let err = BacktraceError::new(fmt::Error);
+    assert_eq!(err.location().line(), line);
+
+    let line = line!() + 1;
+    let err = DynBacktraceError::new(fmt::Error);
+    assert_eq!(err.location().line(), line);
+
+    let line = line!() + 1;
+    let err = Err::<(), _>(fmt::Error).bt().unwrap_err();
+    assert_eq!(err.location().line(), line);
+    assert_eq!(err.location().file(), file!());
+}
+
+#[test]
+fn wrapping_again_keeps_the_first_location() {
+    let err = parse("x").context("parsing").unwrap_err();
+    assert_eq!(err.location().line(), 7);
+    let flat: BacktraceError<ParseIntError> = err.into();
+    assert_eq!(flat.location().line(), 7);
+    let dyn_err = DynBacktraceError::from(flat);
+    assert_eq!(dyn_err.location().line(), 7);
+}
+
+#[test]
+fn report_falls_back_to_location() {
+    set_capture_policy(CapturePolicy::Never);
+    let err = BacktraceError::new(fmt::Error);
+    set_capture_policy(CapturePolicy::Env);
+    assert!(err.frames().is_empty());
+    let report = err.to_string();
+    let expected = format!("    at {} (", err.location());
+    assert!(report.contains(&expected), "{}", report);
+}
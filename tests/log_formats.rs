@@ -0,0 +1,64 @@
+use backtrace_error::{set_capture_policy, CapturePolicy, Context, DynBacktraceError};
+use std::{error::Error, fmt, num::ParseIntError};
+
+#[derive(Debug)]
+struct Outer(ParseIntError);
+
+impl fmt::Display for Outer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("loading \"app.toml\" failed\n  (second line)")
+    }
+}
+
+impl Error for Outer {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+fn fail() -> Result<(), DynBacktraceError> {
+    Err(Outer("x".parse::<i32>().unwrap_err()))?
+}
+
+#[test]
+fn one_line_with_frames() {
+    set_capture_policy(CapturePolicy::Always);
+    let err = fail().context("starting up").unwrap_err();
+
+    let line = err.report().single_line().to_string();
+    assert!(!line.contains('\n'));
+    let parts: Vec<&str> = line.split(" | ").collect();
+    assert_eq!(
+        parts[0],
+        "loading \"app.toml\" failed\\n  (second line): \
+         invalid digit found in string: starting up"
+    );
+    assert!(parts[1].starts_with("log_formats::fail at ./tests/log_formats.rs:20:5"));
+    assert!(parts.len() > 2, "{}", line);
+
+    let logfmt = err.report().logfmt().to_string();
+    assert!(!logfmt.contains('\n'));
+    assert!(
+        logfmt.starts_with(
+            "error=\"loading \\\"app.toml\\\" failed\\n  (second line)\" \
+             type=log_formats::Outer id="
+        ),
+        "{}",
+        logfmt
+    );
+    let location = format!(" location={}:20:5 time=", file!());
+    assert!(logfmt.contains(&location), "{}", logfmt);
+    assert!(logfmt.contains(" cause0=\"invalid digit found in string\""));
+    assert!(logfmt.contains(" context0=\"starting up\""));
+    assert!(logfmt.contains(" frame0=\"log_formats::fail at ./tests/log_formats.rs:20:5\""));
+    assert!(!logfmt.contains(" cause1="));
+
+    let err = fail().context("retrying a | b").unwrap_err();
+    let line = err.report().single_line().to_string();
+    assert!(
+        line.contains(": retrying a \\| b | log_formats::fail at "),
+        "{}",
+        line
+    );
+    set_capture_policy(CapturePolicy::Env);
+}
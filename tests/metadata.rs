@@ -0,0 +1,61 @@
+use backtrace_error::{
+    set_capture_policy, set_sampling, BacktraceError, CapturePolicy, DynBacktraceError, Sampling,
+};
+use std::{fmt, thread, time::SystemTime};
+
+#[test]
+fn metadata_follows_policy() {
+    set_capture_policy(CapturePolicy::Always);
+    let before = SystemTime::now();
+    let err = thread::Builder::new()
+        .name("worker-3".into())
+        .spawn(|| DynBacktraceError::from(fmt::Error))
+        .unwrap()
+        .join()
+        .unwrap();
+    let metadata = err.metadata().unwrap();
+    assert_eq!(metadata.thread_name.as_deref(), Some("worker-3"));
+    assert_ne!(metadata.thread_id, thread::current().id());
+    assert_eq!(metadata.pid, std::process::id());
+    assert!(metadata.time >= before);
+    assert!(metadata.instant.elapsed() < std::time::Duration::from_secs(60));
+
+    let report = err.to_string();
+    let header = report.lines().next().unwrap();
+    let prefix = format!("Error {}, captured 20", err.id());
+    assert!(header.starts_with(&prefix), "{}", header);
+    assert!(
+        header.contains("Z on thread 'worker-3' (ThreadId("),
+        "{}",
+        header
+    );
+    assert!(header.ends_with(&format!("in process {}", std::process::id())));
+
+    // Rewrapping keeps the original capture's metadata.
+    let inner: BacktraceError<fmt::Error> = fmt::Error.into();
+    let time = inner.metadata().unwrap().time;
+    let outer = DynBacktraceError::from(inner);
+    assert_eq!(outer.metadata().unwrap().time, time);
+
+    // Only errors that get a backtrace get metadata.
+    set_sampling(Sampling::OneIn(2));
+    for _ in 0..4 {
+        let err = DynBacktraceError::from(fmt::Error);
+        assert_eq!(err.metadata().is_some(), !err.frames().is_empty());
+    }
+    set_sampling(Sampling::All);
+
+    set_capture_policy(CapturePolicy::Never);
+    let inner: BacktraceError<fmt::Error> = fmt::Error.into();
+    set_capture_policy(CapturePolicy::Always);
+    let outer = DynBacktraceError::from(inner);
+    assert!(outer.metadata().is_none());
+
+    set_capture_policy(CapturePolicy::Never);
+    let err = DynBacktraceError::from(fmt::Error);
+    assert!(err.metadata().is_none());
+    let report = err.to_string();
+    let header = report.lines().next().unwrap();
+    assert_eq!(header, format!("Error {}", err.id()));
+    set_capture_policy(CapturePolicy::Env);
+}
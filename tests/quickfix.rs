@@ -0,0 +1,74 @@
+use backtrace_error::{
+    set_capture_policy, BacktraceError, CapturePolicy, Context, DynBacktraceError,
+};
+use std::{env, fmt, num::ParseIntError};
+
+fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    Ok(s.parse::<i32>()?)
+}
+
+fn caller() -> Result<i32, BacktraceError<ParseIntError>> {
+    parse("x")
+}
+
+#[derive(Debug)]
+struct Outer(fmt::Error);
+
+impl fmt::Display for Outer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("outer failed\non two lines")
+    }
+}
+
+impl std::error::Error for Outer {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[test]
+fn one_line_per_frame_or_wrap_site() {
+    set_capture_policy(CapturePolicy::Always);
+    let line = line!() + 1;
+    let err = caller().context("reading the config").unwrap_err();
+    let quickfix = err.report().quickfix().to_string();
+    let lines: Vec<&str> = quickfix.lines().collect();
+    assert_eq!(
+        lines[0], "tests/quickfix.rs:7:8: error: invalid digit found in string",
+        "{}",
+        quickfix
+    );
+    assert!(lines[1].starts_with("tests/quickfix.rs:11:5: note: called from quickfix::caller"));
+    let here = format!(
+        "tests/quickfix.rs:{}:15: note: called from quickfix::",
+        line
+    );
+    assert!(lines[2].starts_with(&here), "{}", quickfix);
+    assert_eq!(
+        *lines.last().unwrap(),
+        "tests/quickfix.rs:7:8: note: reading the config"
+    );
+    // Every line parses as a location.
+    assert!(lines.iter().all(|l| l.starts_with("tests/quickfix.rs:")));
+
+    env::set_var("BACKTRACE_ERROR_FORMAT", "quickfix");
+    assert_eq!(err.report().stderr().to_string(), quickfix);
+    env::remove_var("BACKTRACE_ERROR_FORMAT");
+    assert!(err.report().stderr().to_string().starts_with("Error "));
+
+    // Without a backtrace, the wrap site stands in for it.
+    set_capture_policy(CapturePolicy::Never);
+    let line = line!() + 1;
+    let err = DynBacktraceError::from(Outer(fmt::Error));
+    set_capture_policy(CapturePolicy::Env);
+    assert!(err.frames().is_empty());
+    let location = format!("{}:{}:15", file!(), line);
+    assert_eq!(
+        err.report().quickfix().to_string(),
+        format!(
+            "{0}: error: outer failed on two lines\n\
+             {0}: note: caused by: an error occurred when formatting an argument\n",
+            location
+        )
+    );
+}
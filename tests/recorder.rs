@@ -0,0 +1,85 @@
+use backtrace_error::{
+    disable_flight_recorder, enable_flight_recorder, install_panic_hook, recent_errors,
+    recent_errors_on_this_thread, set_capture_policy, BacktraceError, CapturePolicy, Context,
+    DynBacktraceError,
+};
+use std::{env, fmt, io, process::Command, thread};
+
+fn swallow(msg: &str) {
+    let _ = DynBacktraceError::from(io::Error::new(io::ErrorKind::Other, msg.to_string()));
+}
+
+#[test]
+fn keeps_the_last_few_errors() {
+    swallow("before");
+    assert!(recent_errors().is_empty(), "off by default");
+
+    enable_flight_recorder(3);
+    for i in 0..5 {
+        swallow(&format!("error {}", i));
+    }
+    thread::Builder::new()
+        .name("worker".into())
+        .spawn(|| swallow("from worker"))
+        .unwrap()
+        .join()
+        .unwrap();
+
+    let here: Vec<String> = recent_errors_on_this_thread()
+        .into_iter()
+        .map(|e| e.message)
+        .collect();
+    assert_eq!(here, ["error 2", "error 3", "error 4"]);
+    let all = recent_errors();
+    let messages: Vec<&str> = all.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(messages, ["error 3", "error 4", "from worker"]);
+    assert_eq!(all[2].thread_name.as_deref(), Some("worker"));
+    assert!(all[0].type_name.ends_with("io::error::Error"));
+    assert!(all[0].id < all[1].id);
+
+    disable_flight_recorder();
+    assert!(recent_errors().is_empty());
+    assert!(recent_errors_on_this_thread().is_empty());
+    enable_flight_recorder(3);
+    assert!(recent_errors_on_this_thread().is_empty());
+
+    // Without backtraces, wrapping again or adding context is still one
+    // error.
+    set_capture_policy(CapturePolicy::Never);
+    let _ = DynBacktraceError::from(BacktraceError::new(fmt::Error));
+    let _ = Err::<(), _>(BacktraceError::new(fmt::Error)).context("again");
+    assert_eq!(recent_errors().len(), 2);
+    set_capture_policy(CapturePolicy::Env);
+    disable_flight_recorder();
+}
+
+#[test]
+fn panic_hook_prints_history_first() {
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["--ignored", "--exact", "panicking_child", "--nocapture"])
+        .env("RECORDER_CHILD", "1")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let history = stderr.find("Recent errors on this thread (oldest first):");
+    let error = stderr.find(": core::fmt::Error: an error occurred");
+    let panic = stderr.find("giving up");
+    assert!(
+        history.is_some() && error.is_some() && panic.is_some(),
+        "{}",
+        stderr
+    );
+    assert!(history < error && error < panic, "{}", stderr);
+}
+
+#[test]
+#[ignore]
+fn panicking_child() {
+    if env::var_os("RECORDER_CHILD").is_none() {
+        return;
+    }
+    enable_flight_recorder(4);
+    install_panic_hook();
+    let _ = DynBacktraceError::from(fmt::Error);
+    panic!("giving up");
+}
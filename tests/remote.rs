@@ -0,0 +1,115 @@
+#![cfg(feature = "serde")]
+
+use backtrace_error::{
+    set_capture_policy, CapturePolicy, Context, DynBacktraceError, ErrorRecord, Frame,
+    RemoteBacktraceError,
+};
+use std::{error::Error, fmt};
+
+#[derive(Debug)]
+struct JobFailed(RemoteBacktraceError);
+
+impl fmt::Display for JobFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("job 7 failed")
+    }
+}
+
+impl Error for JobFailed {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[derive(Debug)]
+struct Io(std::io::Error);
+
+impl fmt::Display for Io {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("reading input failed")
+    }
+}
+
+impl Error for Io {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+fn worker() -> Result<(), DynBacktraceError> {
+    let err = std::io::Error::new(std::io::ErrorKind::Other, "disk on fire");
+    Err(Io(err)).context("running job 7")?
+}
+
+fn transport(err: &DynBacktraceError) -> RemoteBacktraceError {
+    serde_json::from_str(&serde_json::to_string(err).unwrap()).unwrap()
+}
+
+#[test]
+fn remote_reports() {
+    set_capture_policy(CapturePolicy::Always);
+    let local = worker().unwrap_err();
+    let remote = transport(&local);
+    assert_eq!(remote.record(), &ErrorRecord::from(&local));
+    // Instruction pointers aren't sent; everything else about the frames is.
+    let located = |frames: Vec<Frame>| -> Vec<_> {
+        frames
+            .into_iter()
+            .map(|f| (f.symbol, f.file, f.line, f.column))
+            .collect()
+    };
+    assert_eq!(located(remote.frames()), located(local.frames()));
+
+    // On its own it renders exactly as the error did where it was captured.
+    let report = remote.to_string();
+    assert_eq!(
+        report.replace("Remote backtrace:", "Error context:"),
+        local.to_string(),
+    );
+    let sources: Vec<String> = std::iter::successors(remote.source(), |&e| e.source())
+        .map(|e| e.to_string())
+        .collect();
+    assert_eq!(sources, ["disk on fire"]);
+
+    // Wrapped locally, both backtraces are shown.
+    let wrapped = DynBacktraceError::from(remote.clone());
+    let report = wrapped.to_string();
+    let remote_at = report.find("Remote backtrace:\n").unwrap();
+    let local_at = report.find("Local backtrace:\n").unwrap();
+    assert!(remote_at < local_at, "{}", report);
+    assert!(report.contains("Initial error: reading input failed\n"));
+    assert!(report.contains("Caused by:\n    1. disk on fire\n"));
+    assert!(report.contains("Context:\n    running job 7\n"));
+    assert!(report[remote_at..local_at].contains("remote::worker\n"));
+    assert!(report[local_at..].contains("remote::remote_reports\n"));
+    assert!(!report[local_at..].contains("remote::worker\n"));
+    assert_eq!(ErrorRecord::from(&wrapped).message, "reading input failed");
+
+    // As a source, its backtrace goes with it in the causes.
+    let report = DynBacktraceError::from(JobFailed(remote)).to_string();
+    assert!(report.contains(
+        "Initial error: job 7 failed\n\
+         Caused by:\n    1. reading input failed\n       Remote backtrace:\n"
+    ));
+    assert!(report.contains("    2. disk on fire\n"), "{}", report);
+    assert!(report.contains("Local backtrace:\n"));
+    set_capture_policy(CapturePolicy::Env);
+}
+
+#[test]
+fn without_a_backtrace() {
+    let record: ErrorRecord = serde_json::from_value(serde_json::json!({
+        "type": "worker::Error",
+        "message": "job failed",
+        "backtrace": {"status": "disabled"},
+        "location": "src/worker.rs:10:5",
+    }))
+    .unwrap();
+    let remote = RemoteBacktraceError::from(record);
+    assert_eq!(
+        remote.to_string(),
+        "Remote error\n\
+         Initial error: job failed\n\
+         Remote backtrace:\n    at src/worker.rs:10:5 (disabled backtrace)\n"
+    );
+}
@@ -0,0 +1,116 @@
+use backtrace_error::{BacktraceError, DynBacktraceError, ResultExt};
+use std::{fmt, io, num::ParseIntError};
+
+mod common;
+
+#[derive(Debug, PartialEq)]
+struct Payload {
+    name: String,
+    size: usize,
+}
+
+fn parse_typed(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    Ok(s.parse::<i32>()?)
+}
+
+fn parse_dyn(s: &str) -> Result<i32, DynBacktraceError> {
+    Ok(s.parse::<i32>()?)
+}
+
+fn payload_dyn(ok: bool) -> Result<Payload, DynBacktraceError> {
+    if !ok {
+        Err(io::Error::new(io::ErrorKind::NotFound, "no payload"))?;
+    }
+    Ok(Payload {
+        name: "payload".to_string(),
+        size: 42,
+    })
+}
+
+#[test]
+fn typed_unwrap_returns_value() {
+    assert_eq!(parse_typed("17").unwrap_or_backtrace(), 17);
+    assert_eq!(parse_typed("-3").expect_or_backtrace("parse failed"), -3);
+}
+
+#[test]
+#[should_panic(expected = "ResultExt::unwrap_or_backtrace found Err")]
+fn typed_unwrap_panics_on_err() {
+    parse_typed("seventeen").unwrap_or_backtrace();
+}
+
+#[test]
+#[should_panic(expected = "typed parse failed")]
+fn typed_expect_panics_with_message() {
+    parse_typed("seventeen").expect_or_backtrace("typed parse failed");
+}
+
+#[test]
+fn dyn_unwrap_returns_value() {
+    assert_eq!(parse_dyn("99").unwrap_or_backtrace(), 99);
+    let payload = payload_dyn(true).expect_or_backtrace("no payload");
+    assert_eq!(
+        payload,
+        Payload {
+            name: "payload".to_string(),
+            size: 42
+        }
+    );
+}
+
+#[test]
+fn dyn_unwrap_unit() {
+    let res: Result<(), DynBacktraceError> = Ok(());
+    res.unwrap_or_backtrace();
+}
+
+#[test]
+#[should_panic(expected = "ResultExt::unwrap_or_backtrace found Err")]
+fn dyn_unwrap_panics_on_err() {
+    parse_dyn("ninety-nine").unwrap_or_backtrace();
+}
+
+#[test]
+#[should_panic(expected = "dyn payload failed")]
+fn dyn_expect_panics_with_message() {
+    payload_dyn(false).expect_or_backtrace("dyn payload failed");
+}
+
+#[test]
+fn display_includes_initial_error() {
+    let err = parse_typed("x").unwrap_err();
+    let text = err.to_string();
+    assert!(common::body(&text).starts_with("Initial error: invalid digit found in string"));
+
+    let err = payload_dyn(false).unwrap_err();
+    let text = format!("{:?}", err);
+    assert!(common::body(&text).starts_with("Initial error: no payload"));
+}
+
+struct Custom;
+
+impl fmt::Debug for Custom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Custom")
+    }
+}
+
+impl fmt::Display for Custom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("custom failure")
+    }
+}
+
+impl std::error::Error for Custom {}
+
+#[test]
+fn dyn_wraps_custom_errors() {
+    fn fail() -> Result<Vec<u8>, DynBacktraceError> {
+        Err(Custom)?
+    }
+    let err = fail().unwrap_err();
+    assert_eq!(
+        common::body(&err.to_string()).lines().next(),
+        Some("Initial error: custom failure")
+    );
+}
@@ -0,0 +1,118 @@
+use backtrace_error::{
+    sampling, set_capture_policy, set_sampling, BacktraceError, CapturePolicy, DynBacktraceError,
+    SampleKey, Sampling,
+};
+use std::{backtrace::BacktraceStatus, error::Error, fmt, io, num::ParseIntError, thread};
+
+fn captured(err: &DynBacktraceError) -> bool {
+    err.backtrace().status() == BacktraceStatus::Captured
+}
+
+fn at_call_site() -> DynBacktraceError {
+    DynBacktraceError::from(fmt::Error)
+}
+
+fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    Ok(s.parse::<i32>()?)
+}
+
+fn convert(s: &str) -> Result<i32, DynBacktraceError> {
+    Ok(parse(s)?)
+}
+
+#[derive(Debug)]
+struct Burst;
+
+impl fmt::Display for Burst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("burst")
+    }
+}
+
+impl Error for Burst {}
+
+#[test]
+fn sampling_limits_capture() {
+    set_capture_policy(CapturePolicy::Always);
+    assert_eq!(sampling(), Sampling::All);
+    assert!((0..10).all(|_| captured(&DynBacktraceError::from(fmt::Error))));
+
+    set_sampling(Sampling::OneIn(4));
+    assert_eq!(sampling(), Sampling::OneIn(4));
+    let count = (0..40)
+        .filter(|_| captured(&DynBacktraceError::from(fmt::Error)))
+        .count();
+    assert_eq!(count, 10);
+
+    let skipped = (0..4)
+        .map(|_| DynBacktraceError::from(fmt::Error))
+        .find(|err| !captured(err))
+        .unwrap();
+    let report = skipped.to_string();
+    assert!(report.contains("backtrace not sampled"), "{}", report);
+    assert!(!report.contains("disabled backtrace"), "{}", report);
+
+    // Converting with `?` doesn't sample again: the error was sampled, or
+    // not, where it was first wrapped.
+    let errs: Vec<DynBacktraceError> = (0..4).map(|_| convert("x").unwrap_err()).collect();
+    assert_eq!(errs.iter().filter(|err| captured(err)).count(), 1);
+    for err in &errs {
+        if captured(err) {
+            let origin = err.frames().into_iter().find(|f| {
+                f.symbol
+                    .as_deref()
+                    .is_some_and(|s| s.starts_with("sampling::"))
+            });
+            let symbol = origin.and_then(|f| f.symbol).unwrap();
+            assert!(symbol.starts_with("sampling::parse"), "{}", symbol);
+        } else {
+            assert!(err.to_string().contains("backtrace not sampled"));
+        }
+    }
+
+    // Per type: a burst of one type uses up its own limit, not the other's.
+    // These run well within a second, but straddling a second boundary
+    // would reset the count, so only check the upper bound.
+    let per_type = Sampling::PerSecond {
+        limit: 3,
+        key: SampleKey::ErrorType,
+    };
+    set_sampling(per_type);
+    assert_eq!(sampling(), per_type);
+    let fmt_count = (0..20)
+        .filter(|_| captured(&DynBacktraceError::from(fmt::Error)))
+        .count();
+    assert!((1..=6).contains(&fmt_count), "{}", fmt_count);
+    let io_err = DynBacktraceError::from(io::Error::new(io::ErrorKind::Other, "io"));
+    assert!(captured(&io_err));
+
+    // The limit holds across threads wrapping the same type at once.
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            thread::spawn(|| {
+                (0..50)
+                    .filter(|_| captured(&DynBacktraceError::from(Burst)))
+                    .count()
+            })
+        })
+        .collect();
+    let burst_count: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+    assert!((1..=6).contains(&burst_count), "{}", burst_count);
+
+    // Per call site: two sites, same error type.
+    set_sampling(Sampling::PerSecond {
+        limit: 2,
+        key: SampleKey::CallSite,
+    });
+    let site_count = (0..20).filter(|_| captured(&at_call_site())).count();
+    assert!((1..=4).contains(&site_count), "{}", site_count);
+    assert!(captured(&DynBacktraceError::from(fmt::Error)));
+
+    // The capture policy still comes first.
+    set_sampling(Sampling::All);
+    set_capture_policy(CapturePolicy::Never);
+    let err = DynBacktraceError::from(fmt::Error);
+    assert!(!captured(&err));
+    assert!(!err.to_string().contains("backtrace not sampled"));
+    set_capture_policy(CapturePolicy::Env);
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "serde")]
+
+use backtrace_error::{
+    set_capture_policy, BacktraceError, CapturePolicy, Context, DynBacktraceError, ErrorRecord,
+};
+use serde_json::{json, Value};
+use std::num::ParseIntError;
+
+fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    Ok(s.parse::<i32>()?)
+}
+
+#[test]
+fn round_trips_through_json() {
+    set_capture_policy(CapturePolicy::Always);
+    let err = parse("x").context("reading the port").unwrap_err();
+    let json = serde_json::to_value(&err).unwrap();
+    assert_eq!(json["type"], "core::num::error::ParseIntError");
+    assert_eq!(json["message"], "invalid digit found in string");
+    assert_eq!(json["sources"], json!([]));
+    assert_eq!(json["context"], json!(["reading the port"]));
+    assert_eq!(json["backtrace"]["status"], "captured");
+    assert_eq!(json["location"], format!("{}:10:8", file!()));
+    assert_eq!(json["id"], err.id().to_string());
+    assert!(json["metadata"]["time"].as_str().unwrap().ends_with('Z'));
+    assert_eq!(json["metadata"]["pid"], std::process::id());
+    let frames = json["backtrace"]["frames"].as_array().unwrap();
+    let parse_frame = frames
+        .iter()
+        .find(|f| f["symbol"] == "serde::parse")
+        .unwrap();
+    assert_eq!(parse_frame["line"], 10);
+    assert_eq!(parse_frame["col"], 8);
+    assert!(parse_frame["file"].as_str().unwrap().ends_with("serde.rs"));
+
+    let record: ErrorRecord = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(record, ErrorRecord::from(&err));
+    assert_eq!(serde_json::to_value(&record).unwrap(), json);
+
+    // A `DynBacktraceError` around it serializes the same error.
+    let dyn_err = DynBacktraceError::from(err);
+    let record: ErrorRecord =
+        serde_json::from_str(&serde_json::to_string(&dyn_err).unwrap()).unwrap();
+    assert_eq!(record.message, "invalid digit found in string");
+    assert_eq!(record.context, ["reading the port"]);
+    assert_eq!(record.backtrace.frames.len(), frames.len());
+
+    set_capture_policy(CapturePolicy::Never);
+    let json = serde_json::to_value(parse("x").unwrap_err()).unwrap();
+    assert_eq!(
+        json["backtrace"],
+        json!({"status": "disabled", "frames": []})
+    );
+    assert_eq!(json["metadata"], Value::Null);
+    set_capture_policy(CapturePolicy::Env);
+}
+
+#[test]
+fn optional_fields_may_be_missing() {
+    let record: ErrorRecord = serde_json::from_value(json!({
+        "type": "worker::Error",
+        "message": "job failed",
+        "backtrace": {"status": "disabled"},
+    }))
+    .unwrap();
+    assert!(record.sources.is_empty());
+    assert!(record.backtrace.frames.is_empty());
+    assert_eq!(record.metadata, None);
+    assert_eq!(record.location, "");
+}
@@ -0,0 +1,39 @@
+use backtrace_error::{set_capture_policy, CapturePolicy, DynBacktraceError};
+use std::{env, fmt};
+
+fn fail() -> DynBacktraceError {
+    DynBacktraceError::from(fmt::Error) // the failing line
+}
+
+#[test]
+fn snippets_show_source_or_say_why_not() {
+    set_capture_policy(CapturePolicy::Always);
+    let err = fail();
+    let plain = err.to_string();
+    assert!(!plain.contains("the failing line"));
+
+    let report = err.report().snippets(true).to_string();
+    let marked = report
+        .lines()
+        .find(|l| l.trim_start().starts_with("> 5 |"))
+        .unwrap_or_else(|| panic!("{}", report));
+    assert!(marked.ends_with("DynBacktraceError::from(fmt::Error) // the failing line"));
+    assert!(
+        report.contains("  4 | fn fail() -> DynBacktraceError {"),
+        "{}",
+        report
+    );
+    assert!(report.contains("    |     ^\n"), "{}", report);
+    // Rendering again uses the cached file and gives the same output.
+    assert_eq!(err.report().snippets(true).to_string(), report);
+
+    // From `/` every frame counts as the workspace's, including std's,
+    // whose sources aren't on disk.
+    let cwd = env::current_dir().unwrap();
+    env::set_current_dir("/").unwrap();
+    let full = format!("{:#}", err.report().snippets(true));
+    env::set_current_dir(cwd).unwrap();
+    assert!(full.contains("(source not available)"), "{}", full);
+    assert!(full.contains("the failing line"), "{}", full);
+    set_capture_policy(CapturePolicy::Env);
+}
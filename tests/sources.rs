@@ -0,0 +1,146 @@
+use backtrace_error::{register_has_backtrace, BacktraceError, DynBacktraceError};
+use std::{backtrace::Backtrace, error::Error, fmt};
+
+mod common;
+
+use common::Traced;
+
+#[derive(Debug)]
+struct Layer {
+    msg: &'static str,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg)
+    }
+}
+
+impl Error for Layer {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+fn layer(msg: &'static str, source: Option<Box<dyn Error + Send + Sync>>) -> Layer {
+    Layer { msg, source }
+}
+
+#[test]
+fn prints_numbered_source_chain() {
+    let io = std::io::Error::new(std::io::ErrorKind::NotFound, "file missing");
+    let err = BacktraceError::from(layer(
+        "loading config",
+        Some(Box::new(layer("parsing json", Some(Box::new(io))))),
+    ));
+    let report = err.to_string();
+    assert!(common::body(&report).starts_with(
+        "Initial error: loading config\n\
+         Caused by:\n    \
+         1. parsing json\n    \
+         2. file missing\n\
+         Error context:\n"
+    ));
+}
+
+#[test]
+fn no_caused_by_without_sources() {
+    let err = DynBacktraceError::from(layer("alone", None));
+    assert!(!err.to_string().contains("Caused by:"));
+}
+
+#[derive(Debug)]
+struct Loop;
+
+impl fmt::Display for Loop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("loop")
+    }
+}
+
+impl Error for Loop {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self)
+    }
+}
+
+#[test]
+fn detects_cycles() {
+    let err = DynBacktraceError::from(Loop);
+    let report = err.to_string();
+    // The wrapper holds `Loop` through a different vtable than `source()`
+    // hands out, so the cycle may only be seen once it comes round again.
+    let lap = "Caused by:\n    1. <cycle in error sources>\n";
+    let late = "Caused by:\n    1. loop\n    2. <cycle in error sources>\n";
+    assert!(report.contains(lap) || report.contains(late), "{}", report);
+}
+
+/// Forwards its message to the error it wraps, like
+/// `#[error("{0}")] struct Newtype(#[from] fmt::Error)` would.
+#[derive(Debug)]
+struct Newtype(fmt::Error);
+
+impl fmt::Display for Newtype {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for Newtype {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[test]
+fn newtype_source_is_not_a_cycle() {
+    // The field shares the newtype's address, size and message.
+    let err = DynBacktraceError::from(Newtype(fmt::Error));
+    let report = err.to_string();
+    assert!(
+        report.contains(
+            "Initial error: an error occurred when formatting an argument\n\
+             Caused by:\n    1. an error occurred when formatting an argument\n"
+        ),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn long_chains_are_cut_off() {
+    let mut chain = layer("bottom", None);
+    for _ in 0..100 {
+        chain = layer("layer", Some(Box::new(chain)));
+    }
+    let report = DynBacktraceError::from(chain).to_string();
+    assert!(report.contains("    64. layer\n    65. <further sources omitted>\n"));
+    assert!(!report.contains("bottom"));
+}
+
+#[test]
+fn prints_backtraces_of_wrapped_causes() {
+    register_has_backtrace::<Traced>();
+    let traced = Traced(Backtrace::force_capture());
+    let err = DynBacktraceError::from(layer("outer", Some(Box::new(traced))));
+    let report = err.to_string();
+    assert!(report.contains("Caused by:\n    1. traced\n       Error context:\n"));
+
+    // When the outer wrapper reuses that very backtrace it is printed once.
+    let traced = Traced(Backtrace::force_capture());
+    let err = DynBacktraceError::from(traced);
+    let report = err.to_string();
+    assert!(common::body(&report).starts_with("Initial error: traced\nError context:\n"));
+}
+
+#[test]
+fn wrapped_causes_print_their_message_not_their_report() {
+    let inner: BacktraceError<std::io::Error> =
+        std::io::Error::new(std::io::ErrorKind::Other, "disk on fire").into();
+    let err = DynBacktraceError::from(layer("saving", Some(Box::new(inner))));
+    let report = err.to_string();
+    assert!(common::body(&report)
+        .starts_with("Initial error: saving\nCaused by:\n    1. disk on fire\n"));
+    assert_eq!(report.matches("Initial error:").count(), 1);
+}
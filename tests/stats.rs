@@ -0,0 +1,95 @@
+use backtrace_error::{
+    disable_stats, enable_stats, set_capture_policy, set_sampling, stats_snapshot, BacktraceError,
+    CapturePolicy, Context, DynBacktraceError, Sampling,
+};
+use std::{fmt, io};
+
+#[inline(never)]
+fn fail_fmt() -> DynBacktraceError {
+    DynBacktraceError::from(fmt::Error)
+}
+
+#[inline(never)]
+fn fail_io(msg: &str) -> DynBacktraceError {
+    DynBacktraceError::from(io::Error::new(io::ErrorKind::Other, msg.to_string()))
+}
+
+#[test]
+fn registry_tallies_by_fingerprint() {
+    set_capture_policy(CapturePolicy::Always);
+    let _ = fail_fmt();
+    assert!(stats_snapshot().errors.is_empty(), "off by default");
+
+    enable_stats(10);
+    let mut fp_fmt = None;
+    for _ in 0..3 {
+        fp_fmt = Some(fail_fmt().fingerprint());
+    }
+    let io = fail_io("first");
+    let _ = fail_io("second");
+    // Wrapping again is not a new occurrence.
+    let typed = BacktraceError::new(fmt::Error);
+    let _ = DynBacktraceError::from(typed);
+
+    let stats = stats_snapshot();
+    assert_eq!(stats.errors.len(), 3);
+    assert_eq!(stats.errors[2].count, 1);
+    assert_eq!(stats.errors[0].count, 3);
+    assert_eq!(Some(stats.errors[0].fingerprint), fp_fmt);
+    assert!(stats.errors[0].type_name.ends_with("fmt::Error"));
+    assert!(stats.errors[0].first_seen <= stats.errors[0].last_seen);
+    assert!(!stats.errors[0].frames.is_empty());
+    let io_stats = &stats.errors[1];
+    assert_eq!(io_stats.fingerprint, io.fingerprint());
+    assert_eq!(io_stats.count, 2);
+    assert_eq!(io_stats.message, "first");
+
+    let dump = stats.to_string();
+    assert!(dump.starts_with("3 distinct errors\n"), "{}", dump);
+    assert!(
+        dump.contains(&format!("       3x {}", fp_fmt.unwrap())),
+        "{}",
+        dump
+    );
+    assert!(dump.contains("fail_fmt"), "{}", dump);
+
+    // Shrinking the limit evicts the least recently seen.
+    enable_stats(1);
+    let stats = stats_snapshot();
+    assert_eq!(stats.errors.len(), 1);
+    assert_eq!(stats.evicted, 2);
+    let _ = fail_fmt();
+    let stats = stats_snapshot();
+    assert_eq!(stats.errors.len(), 1);
+    assert_eq!(stats.errors[0].fingerprint, fp_fmt.unwrap());
+    assert!(stats.to_string().contains("(3 more evicted)"));
+
+    // Occurrences that sampling skipped are counted with the rest.
+    set_sampling(Sampling::OneIn(2));
+    for _ in 0..4 {
+        let _ = fail_fmt();
+    }
+    set_sampling(Sampling::All);
+    let stats = stats_snapshot();
+    assert_eq!(stats.errors.len(), 1);
+    assert_eq!(stats.errors[0].count, 5);
+
+    disable_stats();
+    let _ = fail_fmt();
+    assert!(stats_snapshot().errors.is_empty());
+
+    // Without backtraces, wrapping again or adding context is still one
+    // occurrence.
+    set_capture_policy(CapturePolicy::Never);
+    enable_stats(10);
+    let _ = DynBacktraceError::from(BacktraceError::new(fmt::Error));
+    let _ = Err::<(), _>(BacktraceError::new(fmt::Error)).context("again");
+    let stats = stats_snapshot();
+    assert_eq!(stats.errors.len(), 2);
+    for stats in &stats.errors {
+        assert_eq!(stats.count, 1);
+        assert_eq!(stats.type_name, "core::fmt::Error");
+    }
+    disable_stats();
+    set_capture_policy(CapturePolicy::Env);
+}
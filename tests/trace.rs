@@ -0,0 +1,58 @@
+use backtrace_error::{BacktraceError, Context, DynBacktraceError, Trace};
+use std::num::ParseIntError;
+
+fn parse(s: &str) -> Result<i32, BacktraceError<ParseIntError>> {
+    Ok(s.parse::<i32>()?)
+}
+
+fn middle() -> Result<i32, BacktraceError<ParseIntError>> {
+    parse("x").trace()
+}
+
+fn outer() -> Result<i32, DynBacktraceError> {
+    middle().trace().map_err(BacktraceError::into_dyn).trace()
+}
+
+#[test]
+fn hops_are_recorded_in_order() {
+    let err = outer().unwrap_err();
+    let lines: Vec<u32> = err.return_trace().iter().map(|l| l.line()).collect();
+    assert_eq!(lines, [9, 13, 13]);
+    assert!(err.return_trace().iter().all(|l| l.file() == file!()));
+}
+
+#[test]
+fn report_lists_hops_after_backtrace() {
+    let err = outer().unwrap_err();
+    let report = err.to_string();
+    let backtrace = report.find("Error context:").unwrap();
+    let trace = report.find("Propagated through:").unwrap();
+    assert!(backtrace < trace, "{}", report);
+    let hop = format!("    at {}:9:", file!());
+    assert!(report[trace..].contains(&hop), "{}", report);
+}
+
+#[test]
+fn trace_survives_flattening_and_rewrapping() {
+    let err = middle().context("parsing").trace().unwrap_err();
+    let flat: BacktraceError<ParseIntError> = err.into();
+    assert_eq!(flat.return_trace().len(), 2);
+
+    // `?` into a DynBacktraceError keeps the inner wrapper's hops, and the
+    // report gathers them with the outer ones.
+    let dyn_err = DynBacktraceError::from(flat);
+    let dyn_err = Err::<(), _>(dyn_err).trace().unwrap_err();
+    assert_eq!(dyn_err.return_trace().len(), 1);
+    let report = dyn_err.to_string();
+    assert_eq!(report.matches(&format!("    at {}:", file!())).count(), 3);
+
+    let typed = dyn_err.downcast::<ParseIntError>().unwrap();
+    assert_eq!(typed.return_trace().len(), 3);
+}
+
+#[test]
+fn no_section_without_hops() {
+    let err = parse("x").unwrap_err();
+    assert!(err.return_trace().is_empty());
+    assert!(!err.to_string().contains("Propagated through:"));
+}